@@ -0,0 +1,36 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+// omnect-ui is a binary crate, the module only depends on regular dependencies
+#[allow(dead_code)]
+#[path = "../src/token_manager.rs"]
+mod token_manager;
+
+use token_manager::TokenManager;
+
+/// verify_token runs on every authenticated request and on websocket connect
+fn verify_token(c: &mut Criterion) {
+    std::env::set_var("CENTRIFUGO_TOKEN_HMAC_SECRET_KEY", "bench-secret-key");
+
+    let data_dir = std::env::temp_dir().join("omnect-ui-bench");
+    std::fs::create_dir_all(&data_dir).unwrap();
+
+    let token_manager = TokenManager::new(Some(std::time::Duration::from_secs(3600)), &data_dir);
+    let token = token_manager.create_token(false).unwrap();
+    let remembered = token_manager.create_token(true).unwrap();
+    let mut forged = token.clone();
+    forged.pop();
+
+    c.bench_function("verify_token", |b| {
+        b.iter(|| token_manager.verify_token(black_box(&token)).unwrap())
+    });
+    c.bench_function("verify_token remembered", |b| {
+        b.iter(|| token_manager.verify_token(black_box(&remembered)).unwrap())
+    });
+    c.bench_function("verify_token forged", |b| {
+        b.iter(|| token_manager.verify_token(black_box(&forged)).unwrap())
+    });
+}
+
+criterion_group!(benches, verify_token);
+criterion_main!(benches);
@@ -9,18 +9,27 @@ centrifugo_port="8000"
 docker build \
   --build-arg=DOCKER_NAMESPACE=omnectweucopsacr.azurecr.io \
   --build-arg=VERSION_RUST_CONTAINER="${rust_version}" \
+  --build-arg=GIT_SHORT_REV="$(git rev-parse --short HEAD)" \
+  --build-arg=BUILD_TIMESTAMP="$(date -u +%Y-%m-%dT%H:%M:%SZ)" \
   -f Dockerfile \
   --progress=plain \
   -t omnect-ui:"local_${omnect_ui_version}" .
 
 # ensure presense of:
 # /tmp/api.sock (normally created by a local instance of omnect-device-service)
+#   (alternatively omnect-device-service can be reached via http by replacing
+#   SOCKET_PATH with e.g. -e DEVICE_SERVICE_URL=http://host.docker.internal:1234)
+#   (or it can be simulated entirely by appending --demo to the docker run command,
+#   which needs neither a socket nor an omnect-device-service instance)
 # ./temp/device_id_cert.pem and temp/device_id_cert_key.pem (certificate and key file as used on device)
+mkdir -p temp/data
 docker run --rm \
   -v $(pwd)/temp:/temp \
   --mount type=bind,source=/tmp/api.sock,target=/temp/api.sock \
+  --mount type=bind,source=/etc/os-release,target=/host/os-release,readonly \
   -u $(id -u):$(id -g) \
   -e RUST_LOG=debug \
+  -e DATA_DIR=/temp/data \
   -e SOCKET_PATH=/temp/api.sock \
   -e SSL_CERT_PATH=/temp/device_id_cert.pem \
   -e SSL_KEY_PATH=/temp/device_id_cert_key.pem \
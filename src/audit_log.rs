@@ -0,0 +1,246 @@
+use actix_web::http::{Method, StatusCode};
+use anyhow::{Context, Result};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Sender},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const AUDIT_LOG_FILE: &str = "audit_log.jsonl";
+const MAX_ENTRIES: usize = 1000;
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 200;
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    Basic,
+    Bearer,
+}
+
+/// (method, route pattern, action, auth method) of the privileged routes
+const AUDITED_ROUTES: [(Method, &str, &str, AuthMethod); 6] = [
+    (Method::POST, "/token/login", "login", AuthMethod::Basic),
+    (
+        Method::POST,
+        "/logout/all",
+        "logout-all",
+        AuthMethod::Bearer,
+    ),
+    (Method::POST, "/reboot", "reboot", AuthMethod::Bearer),
+    (
+        Method::POST,
+        "/reload-network",
+        "reload-network",
+        AuthMethod::Bearer,
+    ),
+    (
+        Method::PUT,
+        "/device/identity",
+        "set-device-identity",
+        AuthMethod::Bearer,
+    ),
+    (
+        Method::POST,
+        "/commands/{name}",
+        "run-command",
+        AuthMethod::Bearer,
+    ),
+];
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuditEntry {
+    /// seconds since unix epoch
+    timestamp: u64,
+    action: String,
+    path: String,
+    auth_method: AuthMethod,
+    peer: String,
+    /// http status of the response, e.g. 401 if the caller was not authorized
+    status: u16,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct AuditPage {
+    total: usize,
+    /// newest first
+    entries: Vec<AuditEntry>,
+}
+
+/// Records the outcome of every request to a privileged route. The most recent
+/// entries are kept in a ring buffer, persisted as json lines in the data dir.
+/// The file is appended to and only rewritten when it grew to twice the
+/// ring buffer size, in order to spare the flash. Writing is done by a
+/// dedicated thread, a request never waits for the file.
+pub struct AuditLog {
+    recent: Mutex<VecDeque<AuditEntry>>,
+    writer: Sender<AuditEntry>,
+}
+
+/// Owns the file, keeps its own copy of the recent entries to compact it.
+struct Writer {
+    recent: VecDeque<AuditEntry>,
+    lines_in_file: usize,
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new(data_dir: &Path) -> Self {
+        let path = data_dir.join(AUDIT_LOG_FILE);
+        let mut recent = VecDeque::with_capacity(MAX_ENTRIES);
+        let mut lines_in_file = 0;
+
+        if let Ok(content) = std::fs::read_to_string(&path) {
+            for line in content.lines() {
+                lines_in_file += 1;
+
+                match serde_json::from_str(line) {
+                    Ok(entry) => push(&mut recent, entry),
+                    Err(e) => warn!("invalid entry in {}: {e}", path.display()),
+                }
+            }
+        }
+
+        let (writer, entries) = mpsc::channel();
+        let mut file_writer = Writer {
+            recent: recent.clone(),
+            lines_in_file,
+            path,
+        };
+
+        std::thread::spawn(move || {
+            for entry in entries {
+                file_writer.write(entry);
+            }
+        });
+
+        AuditLog {
+            recent: Mutex::new(recent),
+            writer,
+        }
+    }
+
+    /// `route` is the matched route pattern, requests to other routes are ignored
+    pub fn record(
+        &self,
+        method: &Method,
+        route: Option<&str>,
+        path: &str,
+        peer: &str,
+        status: StatusCode,
+    ) {
+        let Some((_, _, action, auth_method)) = AUDITED_ROUTES
+            .iter()
+            .find(|(m, r, ..)| m == method && Some(*r) == route)
+        else {
+            return;
+        };
+
+        let entry = AuditEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            action: action.to_string(),
+            path: path.to_string(),
+            auth_method: *auth_method,
+            peer: peer.to_string(),
+            status: status.as_u16(),
+        };
+
+        push(&mut self.recent.lock().unwrap(), entry.clone());
+
+        if self.writer.send(entry).is_err() {
+            error!("audit log: writer stopped");
+        }
+    }
+
+    pub fn page(&self, offset: usize, limit: usize) -> AuditPage {
+        let recent = self.recent.lock().unwrap();
+
+        AuditPage {
+            total: recent.len(),
+            entries: recent
+                .iter()
+                .rev()
+                .skip(offset)
+                .take(limit)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl Writer {
+    fn write(&mut self, entry: AuditEntry) {
+        push(&mut self.recent, entry.clone());
+
+        let result = if self.lines_in_file < 2 * MAX_ENTRIES {
+            self.append(&entry)
+        } else {
+            self.compact()
+        };
+
+        if let Err(e) = result {
+            error!("audit log: {e:#}");
+        }
+    }
+
+    fn append(&mut self, entry: &AuditEntry) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("cannot open {}", self.path.display()))?;
+
+        writeln!(file, "{}", serde_json::to_string(entry)?)
+            .with_context(|| format!("cannot write {}", self.path.display()))?;
+
+        self.lines_in_file += 1;
+
+        Ok(())
+    }
+
+    /// rewrites the file with the recent entries, the file is replaced
+    /// atomically, a power cut must not lose the log
+    fn compact(&mut self) -> Result<()> {
+        let tmp_path = self.path.with_extension("jsonl.tmp");
+        let mut content = String::new();
+
+        for entry in &self.recent {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+
+        let mut file = File::create(&tmp_path)
+            .with_context(|| format!("cannot create {}", tmp_path.display()))?;
+
+        file.write_all(content.as_bytes())
+            .and_then(|_| file.sync_all())
+            .with_context(|| format!("cannot write {}", tmp_path.display()))?;
+
+        std::fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("cannot replace {}", self.path.display()))?;
+
+        self.lines_in_file = self.recent.len();
+
+        Ok(())
+    }
+}
+
+fn push(recent: &mut VecDeque<AuditEntry>, entry: AuditEntry) {
+    if recent.len() == MAX_ENTRIES {
+        recent.pop_front();
+    }
+    recent.push_back(entry);
+}
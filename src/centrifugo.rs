@@ -0,0 +1,204 @@
+use anyhow::{anyhow, bail, Context, Result};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    Request,
+    {body::Bytes, client::conn::http1},
+};
+use hyper_util::rt::TokioIo;
+use log::error;
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, SignatureScheme,
+};
+use serde_json::{json, Value};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use zeroize::Zeroizing;
+
+const API_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connection details of the local centrifugo instance spawned by omnect-ui.
+pub struct Centrifugo {
+    port: u16,
+    api_key: Option<Zeroizing<String>>,
+    tls_config: Arc<ClientConfig>,
+    failed: AtomicBool,
+}
+
+impl Centrifugo {
+    /// centrifugo serves the same certificate as omnect-ui, which is issued for the
+    /// device and not for localhost. That's why connections to centrifugo trust
+    /// exactly this certificate instead of validating a chain.
+    pub fn new(port: u16, cert: CertificateDer<'static>) -> Self {
+        let tls_config = ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier(cert)))
+            .with_no_client_auth();
+
+        Centrifugo {
+            port,
+            api_key: std::env::var("CENTRIFUGO_API_KEY").ok().map(Zeroizing::new),
+            tls_config: Arc::new(tls_config),
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    /// true while centrifugo keeps crashing, omnect-ui then runs without live updates
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn set_failed(&self, failed: bool) {
+        self.failed.store(failed, Ordering::Relaxed);
+    }
+
+    /// true if centrifugo accepts connections
+    pub async fn probe(&self) -> bool {
+        TcpStream::connect(("localhost", self.port)).await.is_ok()
+    }
+
+    pub fn websocket_url(&self) -> String {
+        format!("wss://localhost:{}/connection/websocket", self.port)
+    }
+
+    pub fn tls_config(&self) -> Arc<ClientConfig> {
+        self.tls_config.clone()
+    }
+
+    pub async fn publish(&self, channel: &str, data: Value) -> Result<()> {
+        self.api("publish", json!({ "channel": channel, "data": data }))
+            .await
+            .with_context(|| format!("publish to {channel} failed"))
+    }
+
+    /// disconnects all connections of a user
+    pub async fn disconnect(&self, user: &str) -> Result<()> {
+        self.api("disconnect", json!({ "user": user }))
+            .await
+            .with_context(|| format!("disconnect {user} failed"))
+    }
+
+    /// calls a method of the centrifugo server api. Bounded by a timeout, since
+    /// a hung centrifugo still accepts connections.
+    async fn api(&self, method: &str, params: Value) -> Result<()> {
+        tokio::time::timeout(API_TIMEOUT, self.call(method, params))
+            .await
+            .map_err(|_| anyhow!("{method} timed out after {API_TIMEOUT:?}"))?
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<()> {
+        let Some(api_key) = &self.api_key else {
+            bail!("missing api key");
+        };
+
+        let stream = TcpStream::connect(("localhost", self.port))
+            .await
+            .context("cannot create tcp stream")?;
+
+        let stream = TlsConnector::from(self.tls_config.clone())
+            .connect(ServerName::try_from("localhost")?, stream)
+            .await
+            .context("tls handshake failed")?;
+
+        let (mut sender, conn) = http1::handshake(TokioIo::new(stream))
+            .await
+            .context("handshake failed")?;
+
+        tokio::spawn(async move {
+            if let Err(err) = conn.await {
+                error!("api connection failed: {:?}", err);
+            }
+        });
+
+        let request = Request::builder()
+            .uri(format!("/api/{method}"))
+            .method("POST")
+            .header("Host", "localhost")
+            .header("Content-Type", "application/json")
+            .header("X-API-Key", api_key.as_str())
+            .body(Full::new(Bytes::from(params.to_string())))
+            .context("build request failed")?;
+
+        let res = sender
+            .send_request(request)
+            .await
+            .context("send request failed")?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res
+                .collect()
+                .await
+                .map(|b| b.to_bytes())
+                .unwrap_or_default();
+            bail!("{status}: {}", String::from_utf8_lossy(&body));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct PinnedCertVerifier(CertificateDer<'static>);
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if end_entity.as_ref() == self.0.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &ring::default_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &ring::default_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
@@ -0,0 +1,48 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rustls::pki_types::CertificateDer;
+
+const PEM_LINE_LEN: usize = 64;
+
+/// The certificates omnect-ui serves for TLS. Clients on the LAN can download
+/// the issuing certificates in order to trust the device certificate.
+pub struct CertificateChain {
+    certs: Vec<CertificateDer<'static>>,
+}
+
+impl CertificateChain {
+    /// `certs` starts with the device certificate followed by its issuers
+    pub fn new(certs: Vec<CertificateDer<'static>>) -> Self {
+        CertificateChain { certs }
+    }
+
+    /// the issuing certificates, or the device certificate itself if it has no chain
+    fn ca_certs(&self) -> &[CertificateDer<'static>] {
+        if 1 < self.certs.len() {
+            &self.certs[1..]
+        } else {
+            &self.certs
+        }
+    }
+
+    pub fn pem(&self) -> String {
+        self.ca_certs()
+            .iter()
+            .map(|cert| {
+                let base64 = STANDARD.encode(cert.as_ref());
+                let lines = base64
+                    .as_bytes()
+                    .chunks(PEM_LINE_LEN)
+                    .map(|line| String::from_utf8_lossy(line))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+
+                format!("-----BEGIN CERTIFICATE-----\n{lines}\n-----END CERTIFICATE-----\n")
+            })
+            .collect()
+    }
+
+    /// DER holds a single certificate, so only the root most one is returned
+    pub fn der(&self) -> Option<&[u8]> {
+        self.ca_certs().last().map(|cert| cert.as_ref())
+    }
+}
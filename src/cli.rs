@@ -0,0 +1,24 @@
+use clap::Parser;
+use std::path::PathBuf;
+
+/// Command line options, they take precedence over environment variables and
+/// the config file.
+#[derive(Clone, Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// config file [env: CONFIG_PATH] [default: /data/config/omnect-ui.toml]
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// log filter in RUST_LOG syntax, e.g. "info" or "omnect_ui=debug" [env: RUST_LOG]
+    #[arg(long, value_name = "FILTER")]
+    pub log_level: Option<String>,
+
+    /// port the UI is served on [env: UI_PORT]
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// simulate omnect-device-service instead of connecting to it
+    #[arg(long)]
+    pub demo: bool,
+}
@@ -0,0 +1,370 @@
+use crate::{centrifugo::Centrifugo, config::CommandConfig, problem_details::problem_response};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, HashMap},
+    process::Stdio,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader},
+    process::Command,
+    sync::mpsc,
+};
+
+const COMMAND_OUTPUT_CHANNEL: &str = "CommandOutput";
+/// max bytes of stdout and stderr each returned to the caller
+const MAX_OUTPUT_SIZE: usize = 64 * 1024;
+/// output lines are collected for that long and published at once
+const PUBLISH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// (stream, line) of the command output
+type OutputLine = (&'static str, String);
+
+#[derive(Debug, thiserror::Error)]
+pub enum CommandError {
+    #[error("unknown command: {0}")]
+    NotFound(String),
+    #[error("invalid parameters: {0}")]
+    InvalidParams(String),
+    #[error("command {0} in progress")]
+    InProgress(String),
+    #[error("{0} timed out after {1:?}")]
+    Timeout(String, Duration),
+    #[error("{0} failed: {1}")]
+    Failed(String, std::io::Error),
+}
+
+impl ResponseError for CommandError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            CommandError::NotFound(_) => StatusCode::NOT_FOUND,
+            CommandError::InvalidParams(_) => StatusCode::BAD_REQUEST,
+            CommandError::InProgress(_) => StatusCode::CONFLICT,
+            CommandError::Timeout(..) => StatusCode::GATEWAY_TIMEOUT,
+            CommandError::Failed(..) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        problem_response(self.status_code(), &self.to_string())
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CommandRequest {
+    #[serde(default)]
+    params: HashMap<String, String>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CommandOutput {
+    /// None if the command was terminated by a signal
+    exit_code: Option<i32>,
+    stdout: String,
+    stderr: String,
+    /// true if stdout or stderr exceeded the max output size
+    truncated: bool,
+}
+
+/// Runs the commands configured in the `[commands]` section of the config, a
+/// safe alternative to a shell for support. Only one command runs at a time,
+/// its output lines are published to centrifugo while it is running.
+pub struct CommandRunner {
+    commands: RwLock<BTreeMap<String, CommandConfig>>,
+    running: Mutex<Option<String>>,
+    centrifugo: Arc<Centrifugo>,
+}
+
+impl CommandRunner {
+    pub fn new(commands: BTreeMap<String, CommandConfig>, centrifugo: Arc<Centrifugo>) -> Self {
+        CommandRunner {
+            commands: RwLock::new(commands),
+            running: Mutex::new(None),
+            centrifugo,
+        }
+    }
+
+    pub fn reconfigure(&self, commands: &BTreeMap<String, CommandConfig>) {
+        *self.commands.write().unwrap() = commands.clone();
+    }
+
+    /// `caller` identifies who runs the command in the log
+    pub async fn run(
+        &self,
+        name: &str,
+        request: CommandRequest,
+        caller: &str,
+    ) -> Result<CommandOutput, CommandError> {
+        let Some(command) = self.commands.read().unwrap().get(name).cloned() else {
+            return Err(CommandError::NotFound(name.to_string()));
+        };
+
+        let args = resolve_args(&command, &request.params)?;
+        let _running = self.try_start(name)?;
+
+        info!("command {name} {args:?} started by {caller}");
+
+        let timeout = Duration::from_secs(command.timeout_secs);
+        let mut child = Command::new(&command.program)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| CommandError::Failed(name.to_string(), e))?;
+
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let (lines_tx, lines_rx) = mpsc::unbounded_channel();
+
+        let result = tokio::time::timeout(timeout, async {
+            tokio::join!(
+                child.wait(),
+                collect(name, "stdout", stdout, lines_tx.clone()),
+                collect(name, "stderr", stderr, lines_tx),
+                self.publish_output(name, lines_rx),
+            )
+        })
+        .await;
+
+        let Ok((status, (stdout, stdout_truncated), (stderr, stderr_truncated), ())) = result
+        else {
+            warn!("command {name} timed out after {timeout:?}, killed");
+            return Err(CommandError::Timeout(name.to_string(), timeout));
+        };
+
+        let status = status.map_err(|e| CommandError::Failed(name.to_string(), e))?;
+
+        info!("command {name} finished: {status}");
+
+        Ok(CommandOutput {
+            exit_code: status.code(),
+            stdout,
+            stderr,
+            truncated: stdout_truncated || stderr_truncated,
+        })
+    }
+
+    fn try_start(&self, name: &str) -> Result<RunningGuard<'_>, CommandError> {
+        let mut running = self.running.lock().unwrap();
+
+        if let Some(running) = running.as_ref() {
+            return Err(CommandError::InProgress(running.clone()));
+        }
+
+        *running = Some(name.to_string());
+
+        Ok(RunningGuard { runner: self })
+    }
+
+    /// publishes the output lines in batches, so that a verbose command does
+    /// not wait for a publish request per line
+    async fn publish_output(&self, name: &str, mut lines: mpsc::UnboundedReceiver<OutputLine>) {
+        let mut batch = Vec::new();
+
+        while let Some(line) = lines.recv().await {
+            batch.push(line);
+
+            tokio::time::sleep(PUBLISH_INTERVAL).await;
+
+            while let Ok(line) = lines.try_recv() {
+                batch.push(line);
+            }
+
+            let batch = batch
+                .drain(..)
+                .map(|(stream, line)| json!({ "stream": stream, "line": line }))
+                .collect::<Vec<_>>();
+
+            if let Err(e) = self
+                .centrifugo
+                .publish(
+                    COMMAND_OUTPUT_CHANNEL,
+                    json!({ "command": name, "lines": batch }),
+                )
+                .await
+            {
+                error!("publish command output failed: {e:#}");
+            }
+        }
+    }
+}
+
+/// Clears the running command when dropped.
+struct RunningGuard<'a> {
+    runner: &'a CommandRunner,
+}
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        *self.runner.running.lock().unwrap() = None;
+    }
+}
+
+/// returns the output up to the max output size and whether it was truncated.
+/// The output is read to the end in any case, a command must not block on a
+/// full pipe. Collected lines are passed on to be published.
+async fn collect<R: AsyncRead + Unpin>(
+    name: &str,
+    stream: &'static str,
+    output: Option<R>,
+    lines: mpsc::UnboundedSender<OutputLine>,
+) -> (String, bool) {
+    let mut collected = String::new();
+    let mut truncated = false;
+
+    let Some(output) = output else {
+        return (collected, truncated);
+    };
+
+    let mut reader = BufReader::new(output);
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+
+        // bounded, since a line without newline would otherwise be buffered in full
+        match (&mut reader)
+            .take(MAX_OUTPUT_SIZE as u64)
+            .read_until(b'\n', &mut line)
+            .await
+        {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                error!("command {name}: cannot read {stream}: {e}");
+                break;
+            }
+        }
+
+        if truncated {
+            continue;
+        }
+
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(['\n', '\r']);
+
+        if MAX_OUTPUT_SIZE < collected.len() + text.len() + 1 {
+            truncated = true;
+            continue;
+        }
+
+        collected.push_str(text);
+        collected.push('\n');
+
+        let _ = lines.send((stream, text.to_string()));
+    }
+
+    (collected, truncated)
+}
+
+fn resolve_args(
+    command: &CommandConfig,
+    params: &HashMap<String, String>,
+) -> Result<Vec<String>, CommandError> {
+    if let Some(unknown) = params
+        .keys()
+        .find(|param| !command.params.contains_key(*param))
+    {
+        return Err(CommandError::InvalidParams(format!(
+            "unknown parameter {unknown}"
+        )));
+    }
+
+    command
+        .args
+        .iter()
+        .map(|arg| {
+            let Some(param) = CommandConfig::param(arg) else {
+                return Ok(arg.clone());
+            };
+
+            let Some(value) = params.get(param) else {
+                return Err(CommandError::InvalidParams(format!(
+                    "parameter {param} missing"
+                )));
+            };
+
+            if !command.params[param].contains(value) {
+                return Err(CommandError::InvalidParams(format!(
+                    "{value} is not an allowed value of {param}"
+                )));
+            }
+
+            Ok(value.clone())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> CommandConfig {
+        CommandConfig {
+            program: "/tools/busybox".into(),
+            args: vec!["ifup".to_string(), "{interface}".to_string()],
+            params: BTreeMap::from([(
+                "interface".to_string(),
+                vec!["eth0".to_string(), "wlan0".to_string()],
+            )]),
+            timeout_secs: 30,
+        }
+    }
+
+    fn params(params: &[(&str, &str)]) -> HashMap<String, String> {
+        params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn substitutes_allowed_value() {
+        let args = resolve_args(&command(), &params(&[("interface", "wlan0")])).unwrap();
+
+        assert_eq!(args, ["ifup", "wlan0"]);
+    }
+
+    #[test]
+    fn keeps_fixed_args() {
+        let command = CommandConfig {
+            args: vec!["version".to_string()],
+            params: BTreeMap::new(),
+            ..command()
+        };
+
+        assert_eq!(resolve_args(&command, &params(&[])).unwrap(), ["version"]);
+    }
+
+    #[test]
+    fn rejects_disallowed_value() {
+        let result = resolve_args(&command(), &params(&[("interface", "eth0; reboot")]));
+
+        assert!(matches!(result, Err(CommandError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn rejects_missing_param() {
+        let result = resolve_args(&command(), &params(&[]));
+
+        assert!(matches!(result, Err(CommandError::InvalidParams(_))));
+    }
+
+    #[test]
+    fn rejects_unknown_param() {
+        let result = resolve_args(
+            &command(),
+            &params(&[("interface", "eth0"), ("other", "eth0")]),
+        );
+
+        assert!(matches!(result, Err(CommandError::InvalidParams(_))));
+    }
+}
@@ -0,0 +1,337 @@
+use crate::cli::Cli;
+use anyhow::{ensure, Context, Result};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf, str::FromStr, time::Duration};
+
+const DEFAULT_CONFIG_PATH: &str = "/data/config/omnect-ui.toml";
+const DEFAULT_DATA_DIR: &str = "/data/config";
+const DEFAULT_CENTRIFUGO_PORT: u16 = 8000;
+const DEFAULT_DEVICE_SERVICE_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_DEVICE_SERVICE_RETRIES: u32 = 3;
+const MAX_DEVICE_SERVICE_RETRIES: u32 = 10;
+const DEFAULT_SLOW_REQUEST_THRESHOLD_MS: u64 = 1000;
+const DEFAULT_COMMAND_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_MAX_FAILED_LOGINS: u32 = 5;
+const DEFAULT_LOGIN_LOCKOUT_SECS: u64 = 300;
+
+/// omnect-ui configuration. Values are read from an optional toml file
+/// (`--config` or `CONFIG_PATH`, defaults to /data/config/omnect-ui.toml) and
+/// can be overridden by environment variables and command line options.
+/// Secrets are only read from the environment.
+#[derive(Debug)]
+pub struct AppConfig {
+    pub log_level: Option<String>,
+    /// directory for state that has to survive restarts
+    pub data_dir: PathBuf,
+    pub ui_port: u16,
+    pub centrifugo_port: u16,
+    /// max age of tokens of users that choose "remember me", disabled if None
+    pub remember_me_max_age: Option<Duration>,
+    /// requests taking longer are logged
+    pub slow_request_threshold: Duration,
+    /// failed logins of a peer after which it is locked out
+    pub max_failed_logins: u32,
+    pub login_lockout: Duration,
+    pub tls: TlsConfig,
+    pub server: ServerConfig,
+    pub device_service: DeviceServiceConfig,
+    /// allow-listed commands by name, see [`crate::commands`]
+    pub commands: BTreeMap<String, CommandConfig>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+/// HttpServer tuning, actix-web defaults apply to unset values
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerConfig {
+    /// zero disables keep-alive
+    pub keep_alive: Option<Duration>,
+    /// per worker
+    pub max_connections: Option<usize>,
+    pub client_request_timeout: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub struct DeviceServiceConfig {
+    pub socket_path: Option<PathBuf>,
+    pub url: Option<String>,
+    pub timeout: Duration,
+    pub retries: u32,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    log_level: Option<String>,
+    data_dir: Option<PathBuf>,
+    ui_port: Option<u16>,
+    centrifugo_port: Option<u16>,
+    remember_me_max_age_hours: Option<u64>,
+    slow_request_threshold_ms: Option<u64>,
+    max_failed_logins: Option<u32>,
+    login_lockout_secs: Option<u64>,
+    #[serde(default)]
+    tls: TlsConfigFile,
+    #[serde(default)]
+    server: ServerConfigFile,
+    #[serde(default)]
+    device_service: DeviceServiceConfigFile,
+    #[serde(default)]
+    commands: BTreeMap<String, CommandConfig>,
+}
+
+/// Command that may be run via `POST /commands/{name}`. It is executed
+/// directly, not by a shell. An argument of the form `{param}` is replaced by
+/// the value passed for `param`, which has to be one of its allowed values.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CommandConfig {
+    pub program: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// allowed values per parameter
+    #[serde(default)]
+    pub params: BTreeMap<String, Vec<String>>,
+    #[serde(default = "default_command_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl CommandConfig {
+    /// name of the parameter if `arg` is a placeholder
+    pub fn param(arg: &str) -> Option<&str> {
+        arg.strip_prefix('{')?.strip_suffix('}')
+    }
+}
+
+fn default_command_timeout_secs() -> u64 {
+    DEFAULT_COMMAND_TIMEOUT_SECS
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TlsConfigFile {
+    cert_path: Option<PathBuf>,
+    key_path: Option<PathBuf>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ServerConfigFile {
+    keep_alive_secs: Option<u64>,
+    max_connections: Option<usize>,
+    client_request_timeout_ms: Option<u64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DeviceServiceConfigFile {
+    socket_path: Option<PathBuf>,
+    url: Option<String>,
+    timeout_secs: Option<u64>,
+    retries: Option<u32>,
+}
+
+impl AppConfig {
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let path = cli_or(cli.config.clone(), "CONFIG_PATH", None)?
+            .unwrap_or(PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        let file = match std::fs::read_to_string(&path) {
+            Ok(content) => toml::from_str::<ConfigFile>(&content)
+                .with_context(|| format!("invalid config file {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ConfigFile::default(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("cannot read config file {}", path.display()))
+            }
+        };
+
+        let config = AppConfig {
+            log_level: cli_or(cli.log_level.clone(), "RUST_LOG", file.log_level)?,
+            data_dir: env_or("DATA_DIR", file.data_dir)?.unwrap_or(PathBuf::from(DEFAULT_DATA_DIR)),
+            ui_port: cli_or(cli.port, "UI_PORT", file.ui_port)?.context("UI_PORT missing")?,
+            centrifugo_port: env_or("CENTRIFUGO_PORT", file.centrifugo_port)?
+                .unwrap_or(DEFAULT_CENTRIFUGO_PORT),
+            remember_me_max_age: env_or(
+                "REMEMBER_ME_MAX_AGE_HOURS",
+                file.remember_me_max_age_hours,
+            )?
+            .map(|hours| Duration::from_secs(hours * 3600)),
+            slow_request_threshold: Duration::from_millis(
+                env_or("SLOW_REQUEST_THRESHOLD_MS", file.slow_request_threshold_ms)?
+                    .unwrap_or(DEFAULT_SLOW_REQUEST_THRESHOLD_MS),
+            ),
+            max_failed_logins: env_or("MAX_FAILED_LOGINS", file.max_failed_logins)?
+                .unwrap_or(DEFAULT_MAX_FAILED_LOGINS),
+            login_lockout: Duration::from_secs(
+                env_or("LOGIN_LOCKOUT_SECS", file.login_lockout_secs)?
+                    .unwrap_or(DEFAULT_LOGIN_LOCKOUT_SECS),
+            ),
+            tls: TlsConfig {
+                cert_path: env_or("SSL_CERT_PATH", file.tls.cert_path)?
+                    .context("SSL_CERT_PATH missing")?,
+                key_path: env_or("SSL_KEY_PATH", file.tls.key_path)?
+                    .context("SSL_KEY_PATH missing")?,
+            },
+            server: ServerConfig {
+                keep_alive: env_or("SERVER_KEEP_ALIVE_SECS", file.server.keep_alive_secs)?
+                    .map(Duration::from_secs),
+                max_connections: env_or("SERVER_MAX_CONNECTIONS", file.server.max_connections)?,
+                client_request_timeout: env_or(
+                    "SERVER_CLIENT_REQUEST_TIMEOUT_MS",
+                    file.server.client_request_timeout_ms,
+                )?
+                .map(Duration::from_millis),
+            },
+            device_service: DeviceServiceConfig {
+                socket_path: env_or("SOCKET_PATH", file.device_service.socket_path)?,
+                url: env_or("DEVICE_SERVICE_URL", file.device_service.url)?,
+                timeout: Duration::from_secs(
+                    env_or(
+                        "DEVICE_SERVICE_TIMEOUT_SECS",
+                        file.device_service.timeout_secs,
+                    )?
+                    .unwrap_or(DEFAULT_DEVICE_SERVICE_TIMEOUT_SECS),
+                ),
+                retries: env_or("DEVICE_SERVICE_RETRIES", file.device_service.retries)?
+                    .unwrap_or(DEFAULT_DEVICE_SERVICE_RETRIES),
+            },
+            commands: file.commands,
+        };
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// fails if the data dir does not exist or is not writable, otherwise state
+    /// like the token version would be lost on restart without notice
+    pub fn check_data_dir(&self) -> Result<()> {
+        let probe = self.data_dir.join(".write-probe");
+
+        std::fs::write(&probe, b"")
+            .with_context(|| format!("data dir {} not writable", self.data_dir.display()))?;
+        std::fs::remove_file(&probe).with_context(|| format!("cannot remove {}", probe.display()))
+    }
+
+    /// settings that are only applied on startup, see [`crate::reload_config`]
+    pub fn changes_requiring_restart(&self, other: &AppConfig) -> Vec<&'static str> {
+        let mut changes = vec![];
+
+        if self.data_dir != other.data_dir {
+            changes.push("data_dir");
+        }
+        if self.ui_port != other.ui_port {
+            changes.push("ui_port");
+        }
+        if self.centrifugo_port != other.centrifugo_port {
+            changes.push("centrifugo_port");
+        }
+        if self.remember_me_max_age != other.remember_me_max_age {
+            changes.push("remember_me_max_age");
+        }
+        if self.tls != other.tls {
+            changes.push("tls");
+        }
+        if self.server != other.server {
+            changes.push("server");
+        }
+        if self.device_service.socket_path != other.device_service.socket_path
+            || self.device_service.url != other.device_service.url
+        {
+            changes.push("device_service endpoint");
+        }
+
+        changes
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.ui_port != self.centrifugo_port,
+            "ui_port and centrifugo_port must differ (both {})",
+            self.ui_port
+        );
+        ensure!(
+            !self.device_service.timeout.is_zero(),
+            "device_service.timeout_secs must be greater than 0"
+        );
+        ensure!(
+            self.device_service.retries <= MAX_DEVICE_SERVICE_RETRIES,
+            "device_service.retries must not exceed {MAX_DEVICE_SERVICE_RETRIES}"
+        );
+        ensure!(
+            self.server.max_connections != Some(0),
+            "server.max_connections must be greater than 0"
+        );
+        ensure!(
+            self.remember_me_max_age != Some(Duration::ZERO),
+            "remember_me_max_age_hours must be greater than 0"
+        );
+        ensure!(
+            self.max_failed_logins != 0,
+            "max_failed_logins must be greater than 0"
+        );
+        ensure!(
+            !self.login_lockout.is_zero(),
+            "login_lockout_secs must be greater than 0"
+        );
+
+        for (name, command) in &self.commands {
+            ensure!(
+                command.program.is_absolute(),
+                "commands.{name}.program must be an absolute path"
+            );
+            ensure!(
+                command.timeout_secs != 0,
+                "commands.{name}.timeout_secs must be greater than 0"
+            );
+
+            for param in command
+                .args
+                .iter()
+                .filter_map(|arg| CommandConfig::param(arg))
+            {
+                ensure!(
+                    command
+                        .params
+                        .get(param)
+                        .is_some_and(|values| !values.is_empty()),
+                    "commands.{name}: no allowed values for parameter {param}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// command line options take precedence over environment variables and the config file
+fn cli_or<T>(cli_value: Option<T>, name: &str, file_value: Option<T>) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match cli_value {
+        Some(value) => Ok(Some(value)),
+        None => env_or(name, file_value),
+    }
+}
+
+/// environment variables take precedence over values of the config file
+fn env_or<T>(name: &str, file_value: Option<T>) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("{name} format: {e}")),
+        Err(_) => Ok(file_value),
+    }
+}
@@ -0,0 +1,104 @@
+use crate::centrifugo::Centrifugo;
+use anyhow::{ensure, Context, Result};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+const DEVICE_IDENTITY_FILE: &str = "device_identity.json";
+const DEVICE_IDENTITY_CHANNEL: &str = "DeviceIdentity";
+const MAX_NAME_LEN: usize = 64;
+const MAX_LOCATION_LEN: usize = 128;
+const MAX_NOTES_LEN: usize = 2048;
+/// max size of a json encoded identity in bytes
+pub const MAX_BODY_SIZE: usize = 16 * 1024;
+
+/// Friendly name, location and notes assigned to the device by the user.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DeviceIdentity {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    location: String,
+    #[serde(default)]
+    notes: String,
+}
+
+impl DeviceIdentity {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.name.chars().count() <= MAX_NAME_LEN,
+            "name exceeds {MAX_NAME_LEN} characters"
+        );
+        ensure!(
+            self.location.chars().count() <= MAX_LOCATION_LEN,
+            "location exceeds {MAX_LOCATION_LEN} characters"
+        );
+        ensure!(
+            self.notes.chars().count() <= MAX_NOTES_LEN,
+            "notes exceed {MAX_NOTES_LEN} characters"
+        );
+
+        Ok(())
+    }
+}
+
+/// Persists the device identity in the data dir and publishes changes to centrifugo.
+pub struct DeviceIdentityStore {
+    identity: Mutex<DeviceIdentity>,
+    path: PathBuf,
+    centrifugo: Arc<Centrifugo>,
+}
+
+impl DeviceIdentityStore {
+    pub fn new(data_dir: &Path, centrifugo: Arc<Centrifugo>) -> Self {
+        let path = data_dir.join(DEVICE_IDENTITY_FILE);
+        let identity = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("invalid {}: {e}", path.display());
+                DeviceIdentity::default()
+            }),
+            Err(_) => DeviceIdentity::default(),
+        };
+
+        DeviceIdentityStore {
+            identity: Mutex::new(identity),
+            path,
+            centrifugo,
+        }
+    }
+
+    pub fn get(&self) -> DeviceIdentity {
+        self.identity.lock().unwrap().clone()
+    }
+
+    /// `identity` is expected to be validated by the caller
+    pub async fn set(&self, identity: DeviceIdentity) -> Result<()> {
+        {
+            // file and memory must not diverge on concurrent updates
+            let mut current = self.identity.lock().unwrap();
+
+            std::fs::write(&self.path, serde_json::to_string(&identity)?)
+                .with_context(|| format!("cannot write {}", self.path.display()))?;
+
+            *current = identity.clone();
+        }
+
+        if let Err(e) = self
+            .centrifugo
+            .publish(
+                DEVICE_IDENTITY_CHANNEL,
+                json!({ "device-identity": identity }),
+            )
+            .await
+        {
+            error!("publish device identity failed: {e:#}");
+        }
+
+        Ok(())
+    }
+}
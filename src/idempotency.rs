@@ -0,0 +1,285 @@
+use crate::problem_details::problem_response;
+use actix_web::{
+    body::to_bytes,
+    http::{
+        header::{HeaderValue, CONTENT_TYPE},
+        StatusCode,
+    },
+    web::Bytes,
+    HttpRequest, HttpResponse,
+};
+use log::{debug, error};
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(10 * 60);
+const MAX_KEY_LEN: usize = 255;
+
+struct CachedResponse {
+    status: StatusCode,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+}
+
+impl CachedResponse {
+    fn to_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+
+        if let Some(content_type) = &self.content_type {
+            response.insert_header((CONTENT_TYPE, content_type.clone()));
+        }
+
+        response.body(self.body.clone())
+    }
+}
+
+enum Entry {
+    InFlight,
+    Done(CachedResponse),
+}
+
+/// Replays the response of a state-changing request that is sent again with the
+/// same `Idempotency-Key` header, e.g. by a client retrying after a network error.
+/// Only successful responses are kept, failed requests can be retried.
+#[derive(Default)]
+pub struct IdempotencyCache {
+    entries: Mutex<HashMap<String, (Instant, Entry)>>,
+}
+
+impl IdempotencyCache {
+    pub async fn run<F, Fut>(&self, req: &HttpRequest, operation: &str, f: F) -> HttpResponse
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = HttpResponse>,
+    {
+        let Some(key) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+            return f().await;
+        };
+
+        let key = match key.to_str() {
+            Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => format!("{operation}:{key}"),
+            _ => {
+                return problem_response(
+                    StatusCode::BAD_REQUEST,
+                    &format!(
+                        "{IDEMPOTENCY_KEY_HEADER} must be 1 to {MAX_KEY_LEN} ascii characters"
+                    ),
+                )
+            }
+        };
+
+        {
+            let mut entries = self.entries.lock().unwrap();
+
+            entries.retain(|_, (created, _)| created.elapsed() < IDEMPOTENCY_WINDOW);
+
+            match entries.get(&key) {
+                Some((_, Entry::Done(cached))) => {
+                    debug!("{operation}: replay response of {key}");
+                    return cached.to_response();
+                }
+                Some((_, Entry::InFlight)) => {
+                    return problem_response(
+                        StatusCode::CONFLICT,
+                        &format!("{operation} with this {IDEMPOTENCY_KEY_HEADER} in progress"),
+                    )
+                }
+                None => {
+                    entries.insert(key.clone(), (Instant::now(), Entry::InFlight));
+                }
+            }
+        }
+
+        let mut in_flight = InFlightGuard {
+            cache: self,
+            key: Some(key),
+        };
+
+        let response = f().await;
+
+        if !response.status().is_success() {
+            return response;
+        }
+
+        let status = response.status();
+        let content_type = response.headers().get(CONTENT_TYPE).cloned();
+        let body = match to_bytes(response.into_body()).await {
+            Ok(body) => body,
+            Err(e) => {
+                error!("{operation}: cannot cache response: {e}");
+                return HttpResponse::build(status).finish();
+            }
+        };
+
+        let cached = CachedResponse {
+            status,
+            content_type,
+            body,
+        };
+        let response = cached.to_response();
+
+        if let Some(key) = in_flight.key.take() {
+            self.entries
+                .lock()
+                .unwrap()
+                .insert(key, (Instant::now(), Entry::Done(cached)));
+        }
+
+        response
+    }
+}
+
+/// Removes the in-flight entry of a request that failed or was cancelled.
+struct InFlightGuard<'a> {
+    cache: &'a IdempotencyCache,
+    key: Option<String>,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = &self.key {
+            self.cache.entries.lock().unwrap().remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn request(key: Option<&str>) -> HttpRequest {
+        let mut request = TestRequest::post();
+
+        if let Some(key) = key {
+            request = request.insert_header((IDEMPOTENCY_KEY_HEADER, key));
+        }
+
+        request.to_http_request()
+    }
+
+    async fn body(response: HttpResponse) -> Bytes {
+        to_bytes(response.into_body()).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn replays_successful_response() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicU32::new(0);
+        let req = request(Some("key"));
+
+        for _ in 0..2 {
+            let response = cache
+                .run(&req, "reboot", || async {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    HttpResponse::Ok().body("done")
+                })
+                .await;
+
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(body(response).await, "done");
+        }
+
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[actix_web::test]
+    async fn runs_every_request_without_key() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicU32::new(0);
+
+        for _ in 0..2 {
+            cache
+                .run(&request(None), "reboot", || async {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    HttpResponse::Ok().finish()
+                })
+                .await;
+        }
+
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[actix_web::test]
+    async fn does_not_cache_failures() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicU32::new(0);
+        let req = request(Some("key"));
+
+        for _ in 0..2 {
+            let response = cache
+                .run(&req, "reboot", || async {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    HttpResponse::ServiceUnavailable().finish()
+                })
+                .await;
+
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        }
+
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[actix_web::test]
+    async fn keys_are_per_operation() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicU32::new(0);
+        let req = request(Some("key"));
+
+        for operation in ["reboot", "reload-network"] {
+            cache
+                .run(&req, operation, || async {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    HttpResponse::Ok().finish()
+                })
+                .await;
+        }
+
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[actix_web::test]
+    async fn rejects_duplicate_in_flight() {
+        let cache = IdempotencyCache::default();
+        let req = request(Some("key"));
+
+        let (first, second) = tokio::join!(
+            cache.run(&req, "reboot", || async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                HttpResponse::Ok().finish()
+            }),
+            cache.run(&req, "reboot", || async { HttpResponse::Ok().finish() }),
+        );
+
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            second.headers().get(CONTENT_TYPE).unwrap(),
+            "application/problem+json"
+        );
+    }
+
+    #[actix_web::test]
+    async fn rejects_invalid_key() {
+        let cache = IdempotencyCache::default();
+        let key = "k".repeat(MAX_KEY_LEN + 1);
+
+        let response = cache
+            .run(&request(Some(&key)), "reboot", || async {
+                HttpResponse::Ok().finish()
+            })
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "application/problem+json"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use env_logger::{Builder, Logger, Target};
+use log::{Log, Metadata, Record};
+use std::{
+    io::Write,
+    sync::{OnceLock, RwLock},
+};
+
+static LOGGER: OnceLock<ReloadableLogger> = OnceLock::new();
+
+/// env_logger cannot change its filter once initialized, so the actual logger
+/// is wrapped in order to be replaced on config reload.
+struct ReloadableLogger(RwLock<Logger>);
+
+impl Log for ReloadableLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.read().unwrap().enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.0.read().unwrap().log(record)
+    }
+
+    fn flush(&self) {
+        self.0.read().unwrap().flush()
+    }
+}
+
+/// `filter` uses the RUST_LOG syntax, e.g. "info" or "omnect_ui=debug"
+pub fn init(filter: Option<&str>) {
+    let logger = LOGGER.get_or_init(|| ReloadableLogger(RwLock::new(build(filter))));
+
+    log::set_logger(logger).expect("logger already initialized");
+    log::set_max_level(logger.0.read().unwrap().filter());
+}
+
+pub fn set_filter(filter: Option<&str>) {
+    if let Some(logger) = LOGGER.get() {
+        let new_logger = build(filter);
+        log::set_max_level(new_logger.filter());
+        *logger.0.write().unwrap() = new_logger;
+    }
+}
+
+fn build(filter: Option<&str>) -> Logger {
+    let default_filter = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "info"
+    };
+
+    let mut builder = Builder::new();
+
+    builder.parse_filters(filter.unwrap_or(default_filter));
+
+    builder.format(|f, record| match record.level() {
+        log::Level::Error => {
+            eprintln!("{}", record.args());
+            Ok(())
+        }
+        _ => {
+            writeln!(f, "{}", record.args())
+        }
+    });
+
+    builder.target(Target::Stdout).build()
+}
@@ -0,0 +1,83 @@
+use crate::centrifugo::Centrifugo;
+use log::error;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const MAX_RECENT_ATTEMPTS: usize = 20;
+const SECURITY_CHANNEL: &str = "Security";
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LoginOutcome {
+    Success,
+    Failure,
+    /// rejected without verifying the credentials
+    LockedOut,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LoginAttempt {
+    /// seconds since unix epoch
+    timestamp: u64,
+    peer: String,
+    /// only set on success, a failed attempt might carry a mistyped password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    outcome: LoginOutcome,
+}
+
+/// Keeps the most recent login attempts and publishes them to centrifugo, so
+/// that a logged in user gets aware of failed attempts and lockouts. Publishing is done in
+/// the background, a login never waits for centrifugo.
+pub struct LoginAttempts {
+    recent: Mutex<VecDeque<LoginAttempt>>,
+    centrifugo: Arc<Centrifugo>,
+}
+
+impl LoginAttempts {
+    pub fn new(centrifugo: Arc<Centrifugo>) -> Self {
+        LoginAttempts {
+            recent: Mutex::new(VecDeque::with_capacity(MAX_RECENT_ATTEMPTS)),
+            centrifugo,
+        }
+    }
+
+    pub fn record(&self, peer: &str, user: &str, outcome: LoginOutcome) {
+        let attempts = {
+            let mut recent = self.recent.lock().unwrap();
+
+            if recent.len() == MAX_RECENT_ATTEMPTS {
+                recent.pop_front();
+            }
+
+            recent.push_back(LoginAttempt {
+                timestamp: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                peer: peer.to_string(),
+                user: (outcome == LoginOutcome::Success).then(|| user.to_string()),
+                outcome,
+            });
+
+            recent.iter().cloned().collect::<Vec<_>>()
+        };
+
+        let centrifugo = self.centrifugo.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = centrifugo
+                .publish(SECURITY_CHANNEL, json!({ "login-attempts": attempts }))
+                .await
+            {
+                error!("publish login attempts failed: {e:#}");
+            }
+        });
+    }
+}
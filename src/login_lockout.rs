@@ -0,0 +1,334 @@
+use anyhow::{Context, Result};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        mpsc::{self, Sender},
+        Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const LOGIN_LOCKOUT_FILE: &str = "login_lockout.json";
+/// peers failing longest ago are forgotten beyond that
+const MAX_PEERS: usize = 1000;
+
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct PeerFailures {
+    failures: u32,
+    /// seconds since unix epoch
+    last_failure: u64,
+    locked_until: Option<u64>,
+}
+
+#[derive(Clone, Copy)]
+struct Settings {
+    max_failures: u32,
+    lockout: Duration,
+}
+
+/// Locks out peers after too many failed logins for a cooldown, so that the
+/// credentials cannot be guessed by hammering the login. Lockouts are
+/// persisted in the data dir, a restart does not lift a lockout. Only the
+/// start and end of a lockout are written, by a dedicated thread.
+pub struct LoginLockout {
+    peers: Mutex<HashMap<String, PeerFailures>>,
+    settings: Mutex<Settings>,
+    writer: Sender<HashMap<String, PeerFailures>>,
+}
+
+impl LoginLockout {
+    pub fn new(data_dir: &Path, max_failures: u32, lockout: Duration) -> Self {
+        let path = data_dir.join(LOGIN_LOCKOUT_FILE);
+        let peers = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("invalid {}: {e}", path.display());
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+        let (writer, lockouts) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            while let Ok(mut locked) = lockouts.recv() {
+                // only the latest lockouts matter
+                while let Ok(newer) = lockouts.try_recv() {
+                    locked = newer;
+                }
+
+                if let Err(e) = persist(&path, &locked) {
+                    error!("login lockout: {e:#}");
+                }
+            }
+        });
+
+        LoginLockout {
+            peers: Mutex::new(peers),
+            settings: Mutex::new(Settings {
+                max_failures,
+                lockout,
+            }),
+            writer,
+        }
+    }
+
+    pub fn reconfigure(&self, max_failures: u32, lockout: Duration) {
+        *self.settings.lock().unwrap() = Settings {
+            max_failures,
+            lockout,
+        };
+    }
+
+    /// counts a login attempt of `peer`, returns the remaining lockout time if
+    /// `peer` is locked out. Checking and counting is done at once, so that
+    /// concurrent attempts cannot pass the check before their failures are
+    /// counted. The attempt counts as failure unless released by
+    /// `record_success`.
+    pub fn check(&self, peer: &str) -> Result<(), Duration> {
+        self.attempt(peer, now())
+    }
+
+    fn attempt(&self, peer: &str, now: u64) -> Result<(), Duration> {
+        let Settings {
+            max_failures,
+            lockout,
+        } = *self.settings.lock().unwrap();
+        let mut peers = self.peers.lock().unwrap();
+        let mut lockouts_changed = false;
+
+        // failures older than the lockout are forgotten
+        peers.retain(|_, failures| {
+            let keep = now < failures.last_failure + lockout.as_secs()
+                || failures.locked_until.is_some_and(|until| now < until);
+
+            lockouts_changed |= !keep && failures.locked_until.is_some();
+            keep
+        });
+
+        if !peers.contains_key(peer) && MAX_PEERS <= peers.len() {
+            let oldest = peers
+                .iter()
+                .min_by_key(|(_, failures)| failures.last_failure)
+                .map(|(peer, _)| peer.clone());
+
+            if let Some(failures) = oldest.and_then(|oldest| peers.remove(&oldest)) {
+                lockouts_changed |= failures.locked_until.is_some();
+            }
+        }
+
+        let failures = peers.entry(peer.to_string()).or_insert(PeerFailures {
+            failures: 0,
+            last_failure: now,
+            locked_until: None,
+        });
+
+        let result = match failures.locked_until {
+            Some(locked_until) if now < locked_until => {
+                Err(Duration::from_secs(locked_until - now))
+            }
+            locked_until => {
+                lockouts_changed |= locked_until.is_some();
+                failures.failures += 1;
+                failures.last_failure = now;
+                failures.locked_until = None;
+
+                if max_failures <= failures.failures {
+                    warn!(
+                        "{peer} locked out for {lockout:?} after {} failed logins",
+                        failures.failures
+                    );
+                    failures.failures = 0;
+                    failures.locked_until = Some(now + lockout.as_secs());
+                    lockouts_changed = true;
+                }
+
+                Ok(())
+            }
+        };
+
+        if lockouts_changed {
+            self.persist(&peers);
+        }
+
+        result
+    }
+
+    /// releases the attempt counted by `check`
+    pub fn record_success(&self, peer: &str) {
+        let mut peers = self.peers.lock().unwrap();
+
+        if peers
+            .remove(peer)
+            .is_some_and(|failures| failures.locked_until.is_some())
+        {
+            self.persist(&peers);
+        }
+    }
+
+    /// hands the current lockouts to the writer
+    fn persist(&self, peers: &HashMap<String, PeerFailures>) {
+        let locked = peers
+            .iter()
+            .filter(|(_, failures)| failures.locked_until.is_some())
+            .map(|(peer, failures)| (peer.clone(), *failures))
+            .collect();
+
+        if self.writer.send(locked).is_err() {
+            error!("login lockout: writer stopped");
+        }
+    }
+}
+
+/// replaces the file atomically, a power cut must not lift the lockouts
+fn persist(path: &Path, locked: &HashMap<String, PeerFailures>) -> Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+
+    std::fs::write(&tmp_path, serde_json::to_string(locked)?)
+        .with_context(|| format!("cannot write {}", tmp_path.display()))?;
+    std::fs::rename(&tmp_path, path).with_context(|| format!("cannot replace {}", path.display()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const MAX_FAILURES: u32 = 3;
+    const LOCKOUT: Duration = Duration::from_secs(300);
+    const NOW: u64 = 1_000_000;
+
+    fn lockout(name: &str) -> (LoginLockout, PathBuf) {
+        let data_dir =
+            std::env::temp_dir().join(format!("login-lockout-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        std::fs::create_dir_all(&data_dir).unwrap();
+
+        (
+            LoginLockout::new(&data_dir, MAX_FAILURES, LOCKOUT),
+            data_dir,
+        )
+    }
+
+    #[test]
+    fn locks_out_after_max_failures() {
+        let (lockout, data_dir) = lockout("max-failures");
+
+        for _ in 0..MAX_FAILURES {
+            assert!(lockout.attempt("peer", NOW).is_ok());
+        }
+
+        assert_eq!(
+            lockout.attempt("peer", NOW + 10),
+            Err(LOCKOUT - Duration::from_secs(10))
+        );
+        assert!(lockout.attempt("other", NOW).is_ok());
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn success_releases_attempt() {
+        let (lockout, data_dir) = lockout("success");
+
+        for _ in 0..2 * MAX_FAILURES {
+            assert!(lockout.attempt("peer", NOW).is_ok());
+            lockout.record_success("peer");
+        }
+
+        assert!(lockout.peers.lock().unwrap().is_empty());
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn lockout_expires() {
+        let (lockout, data_dir) = lockout("expiry");
+
+        for _ in 0..MAX_FAILURES {
+            assert!(lockout.attempt("peer", NOW).is_ok());
+        }
+
+        let locked_until = NOW + LOCKOUT.as_secs();
+
+        assert!(lockout.attempt("peer", locked_until - 1).is_err());
+        assert!(lockout.attempt("peer", locked_until).is_ok());
+        assert_eq!(lockout.peers.lock().unwrap()["peer"].failures, 1);
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn old_failures_are_forgotten() {
+        let (lockout, data_dir) = lockout("retain");
+
+        for _ in 1..MAX_FAILURES {
+            assert!(lockout.attempt("peer", NOW).is_ok());
+        }
+        assert!(lockout.attempt("other", NOW + 1).is_ok());
+
+        // "peer" failed long enough ago, "other" did not
+        let later = NOW + LOCKOUT.as_secs();
+
+        assert!(lockout.attempt("peer", later).is_ok());
+
+        let peers = lockout.peers.lock().unwrap();
+
+        assert_eq!(peers["peer"].failures, 1);
+        assert_eq!(peers["other"].failures, 1);
+
+        drop(peers);
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn lockout_survives_restart() {
+        let (lockout, data_dir) = lockout("restart");
+
+        for _ in 0..MAX_FAILURES {
+            assert!(lockout.attempt("peer", NOW).is_ok());
+        }
+
+        // written in the background
+        for _ in 0..100 {
+            if data_dir.join(LOGIN_LOCKOUT_FILE).exists() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let restarted = LoginLockout::new(&data_dir, MAX_FAILURES, LOCKOUT);
+
+        assert!(restarted.attempt("peer", NOW + 1).is_err());
+
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn peers_are_bounded() {
+        let (lockout, data_dir) = lockout("bounded");
+
+        // within the lockout, so that no failures are forgotten by age
+        for peer in 0..MAX_PEERS as u64 + 10 {
+            assert!(lockout.attempt(&peer.to_string(), NOW + peer / 10).is_ok());
+        }
+
+        let peers = lockout.peers.lock().unwrap();
+
+        assert_eq!(peers.len(), MAX_PEERS);
+        assert!((0..10).all(|peer| !peers.contains_key(&peer.to_string())));
+
+        drop(peers);
+        std::fs::remove_dir_all(data_dir).unwrap();
+    }
+}
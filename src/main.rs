@@ -1,63 +1,104 @@
+mod audit_log;
+mod centrifugo;
+mod certificate_chain;
+mod cli;
+mod commands;
+mod config;
+mod device_identity;
+mod idempotency;
+mod logging;
+mod login_attempts;
+mod login_lockout;
+mod omnect_device_service_client;
+mod operation_lock;
+mod problem_details;
+mod process_supervisor;
+mod request_metrics;
+mod simulation;
+mod system_os;
+mod telemetry;
+mod token_manager;
+mod version;
+mod websocket;
+
 use actix_files::{Files, NamedFile};
-use actix_web::{http::StatusCode, web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{
+    dev::Service, http::StatusCode, web, App, HttpRequest, HttpResponse, HttpServer, Responder,
+    ResponseError,
+};
 use actix_web_httpauth::extractors::{basic::BasicAuth, bearer::BearerAuth};
-use anyhow::{Context, Result};
-use env_logger::{Builder, Env, Target};
-use http_body_util::{BodyExt, Empty};
-use hyper::{
-    Request,
-    {body::Bytes, client::conn::http1},
+use audit_log::AuditLog;
+use centrifugo::Centrifugo;
+use certificate_chain::CertificateChain;
+use clap::Parser;
+use cli::Cli;
+use commands::{CommandRequest, CommandRunner};
+use config::AppConfig;
+use device_identity::{DeviceIdentity, DeviceIdentityStore};
+use idempotency::IdempotencyCache;
+use log::{debug, error, info, warn};
+use login_attempts::{LoginAttempts, LoginOutcome};
+use login_lockout::LoginLockout;
+use omnect_device_service_client::OmnectDeviceServiceClient;
+use process_supervisor::{ProcessSupervisor, RestartPolicy};
+use request_metrics::RequestMetrics;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use simulation::Simulation;
+use std::{
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use hyper_util::rt::TokioIo;
-use jwt_simple::prelude::*;
-use log::{debug, error, info};
-use std::io::Write;
-use tokio::{net::UnixStream, process::Command};
-
-const TOKEN_EXPIRE_HOURES: u64 = 2;
+use subtle::ConstantTimeEq;
+use system_os::OsDetails;
+use telemetry::TelemetryHistory;
+use token_manager::TokenManager;
+use tokio::signal::unix::{signal, SignalKind};
+use version::VersionInfo;
+use zeroize::Zeroizing;
+
+const DEFAULT_BODY_LIMIT: usize = 4 * 1024;
+const CENTRIFUGO_PROBE_INTERVAL: Duration = Duration::from_secs(10);
+const CENTRIFUGO_MAX_RESTARTS: u32 = 5;
+/// delay before centrifugo is started again once it was given up
+const CENTRIFUGO_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 #[actix_web::main]
 async fn main() {
     log_panics::init();
 
-    let mut builder = if cfg!(debug_assertions) {
-        Builder::from_env(Env::default().default_filter_or("debug"))
-    } else {
-        Builder::from_env(Env::default().default_filter_or("info"))
-    };
-
-    builder.format(|f, record| match record.level() {
-        log::Level::Error => {
-            eprintln!("{}", record.args());
-            Ok(())
-        }
-        _ => {
-            writeln!(f, "{}", record.args())
-        }
-    });
+    let cli = Cli::parse();
+    let config = AppConfig::load(&cli);
 
-    builder.target(Target::Stdout).init();
+    logging::init(config.as_ref().ok().and_then(|c| c.log_level.as_deref()));
 
     info!("module version: {}", env!("CARGO_PKG_VERSION"));
 
-    let ui_port = std::env::var("UI_PORT")
-        .expect("UI_PORT missing")
-        .parse::<u64>()
-        .expect("UI_PORT format");
+    let config = config.expect("invalid config");
+
+    config.check_data_dir().expect("invalid data dir");
+
+    debug!("config: {config:?}");
 
     let mut certs_file = std::io::BufReader::new(
-        std::fs::File::open(std::env::var("SSL_CERT_PATH").expect("SSL_CERT_PATH missing"))
-            .expect("read certs_file"),
-    );
-    let mut key_file = std::io::BufReader::new(
-        std::fs::File::open(std::env::var("SSL_KEY_PATH").expect("SSL_KEY_PATH missing"))
-            .expect("read key_file"),
+        std::fs::File::open(&config.tls.cert_path).expect("read certs_file"),
     );
+    let mut key_file =
+        std::io::BufReader::new(std::fs::File::open(&config.tls.key_path).expect("read key_file"));
 
     let tls_certs = rustls_pemfile::certs(&mut certs_file)
         .collect::<Result<Vec<_>, _>>()
         .expect("failed to parse cert pem");
 
+    let certificate_chain = web::Data::new(CertificateChain::new(tls_certs.clone()));
+
+    let centrifugo = web::Data::new(Centrifugo::new(
+        config.centrifugo_port,
+        tls_certs.first().expect("no certs found").clone(),
+    ));
+
     let tls_key = rustls_pemfile::rsa_private_keys(&mut key_file)
         .next()
         .expect("no keys found")
@@ -69,13 +110,163 @@ async fn main() {
         .with_single_cert(tls_certs, rustls::pki_types::PrivateKeyDer::Pkcs1(tls_key))
         .expect("invalid tls config");
 
-    let server = HttpServer::new(move || {
+    let centrifugo_path = std::fs::canonicalize("centrifugo").expect("centrifugo not found");
+
+    let device_service_client = if cli.demo {
+        info!("demo mode: omnect-device-service is simulated");
+        OmnectDeviceServiceClient::simulated(
+            Simulation::new(centrifugo.clone().into_inner()),
+            &config.device_service,
+        )
+    } else {
+        OmnectDeviceServiceClient::new(&config.device_service)
+            .expect("failed to create device service client")
+    };
+
+    let device_service_client = web::Data::new(device_service_client);
+    let login_attempts = web::Data::new(LoginAttempts::new(centrifugo.clone().into_inner()));
+    let device_identity = web::Data::new(DeviceIdentityStore::new(
+        &config.data_dir,
+        centrifugo.clone().into_inner(),
+    ));
+    let command_runner = web::Data::new(CommandRunner::new(
+        config.commands.clone(),
+        centrifugo.clone().into_inner(),
+    ));
+    let login_lockout = web::Data::new(LoginLockout::new(
+        &config.data_dir,
+        config.max_failed_logins,
+        config.login_lockout,
+    ));
+    let audit_log = web::Data::new(AuditLog::new(&config.data_dir));
+    let idempotency_cache = web::Data::new(IdempotencyCache::default());
+    let request_metrics = web::Data::new(RequestMetrics::new(config.slow_request_threshold));
+    let telemetry_history = web::Data::new(TelemetryHistory::new(&config.data_dir));
+    let token_manager = web::Data::new(TokenManager::new(
+        config.remember_me_max_age,
+        &config.data_dir,
+    ));
+
+    let version_info = web::Data::new(
+        VersionInfo::new(
+            &centrifugo_path,
+            cli.demo,
+            config.remember_me_max_age.is_some(),
+        )
+        .await,
+    );
+
+    tokio::spawn(telemetry::sample_telemetry(
+        telemetry_history.clone().into_inner(),
+    ));
+
+    tokio::spawn(omnect_device_service_client::watch_reachability(
+        device_service_client.clone().into_inner(),
+        centrifugo.clone().into_inner(),
+    ));
+
+    let bind_address = format!("0.0.0.0:{}", config.ui_port);
+    let server_config = config.server.clone();
+
+    tokio::spawn(reload_config(
+        cli,
+        config,
+        device_service_client.clone().into_inner(),
+        request_metrics.clone().into_inner(),
+        command_runner.clone().into_inner(),
+        login_lockout.clone().into_inner(),
+    ));
+
+    tokio::spawn(run_centrifugo(
+        centrifugo.clone().into_inner(),
+        centrifugo_path,
+    ));
+
+    let mut server = HttpServer::new(move || {
+        let metrics = request_metrics.clone();
+        let audit = audit_log.clone();
+
         App::new()
+            .wrap_fn(move |req, srv| {
+                let audit = audit.clone();
+                let method = req.method().clone();
+                let path = req.path().to_string();
+                let peer = req
+                    .peer_addr()
+                    .map(|addr| addr.ip().to_string())
+                    .unwrap_or("unknown".to_string());
+                let response = srv.call(req);
+
+                async move {
+                    let response = response.await?;
+                    audit.record(
+                        &method,
+                        response.request().match_pattern().as_deref(),
+                        &path,
+                        &peer,
+                        response.status(),
+                    );
+                    Ok(response)
+                }
+            })
+            .wrap_fn(move |req, srv| {
+                let metrics = metrics.clone();
+                let start = Instant::now();
+                let method = req.method().clone();
+                let path = req.path().to_string();
+                let response = srv.call(req);
+
+                async move {
+                    let response = response.await?;
+                    metrics.record(
+                        &method,
+                        response.request().match_pattern().as_deref(),
+                        &path,
+                        response.status(),
+                        start.elapsed(),
+                    );
+                    Ok(response)
+                }
+            })
+            .app_data(audit_log.clone())
+            .app_data(centrifugo.clone())
+            .app_data(certificate_chain.clone())
+            .app_data(device_service_client.clone())
+            .app_data(device_identity.clone())
+            .app_data(command_runner.clone())
+            .app_data(idempotency_cache.clone())
+            .app_data(request_metrics.clone())
+            .app_data(telemetry_history.clone())
+            // no route expects large bodies, routes that do raise the limits for their resource
+            .app_data(web::JsonConfig::default().limit(DEFAULT_BODY_LIMIT))
+            .app_data(web::PayloadConfig::new(DEFAULT_BODY_LIMIT))
+            .app_data(login_attempts.clone())
+            .app_data(login_lockout.clone())
+            .app_data(token_manager.clone())
+            .app_data(version_info.clone())
             .route("/", web::get().to(index))
+            .route("/healthcheck", web::get().to(healthcheck))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz))
+            .route("/version", web::get().to(version))
+            .route("/certificate/chain", web::get().to(get_certificate_chain))
+            .route("/metrics/latency", web::get().to(latency_metrics))
+            .route("/system/os", web::get().to(system_os))
+            .route("/audit-log", web::get().to(get_audit_log))
+            .route("/telemetry/history", web::get().to(get_telemetry_history))
+            .service(
+                web::resource("/device/identity")
+                    .app_data(web::JsonConfig::default().limit(device_identity::MAX_BODY_SIZE))
+                    .get(get_device_identity)
+                    .put(set_device_identity),
+            )
             .route("/token/login", web::post().to(login_token))
             .route("/token/refresh", web::get().to(refresh_token))
+            .route("/logout/all", web::post().to(logout_all))
             .route("/reboot", web::post().to(reboot))
             .route("/reload-network", web::post().to(reload_network))
+            .route("/commands/{name}", web::post().to(run_command))
+            .route("/ws", web::get().to(websocket::ws))
             .service(
                 Files::new(
                     "/static",
@@ -83,22 +274,28 @@ async fn main() {
                 )
                 .show_files_listing(),
             )
-    })
-    .bind_rustls_0_22(format!("0.0.0.0:{ui_port}"), tls_config)
-    .expect("bind_rustls")
-    .disable_signals()
-    .run();
+    });
 
-    let server_handle = server.handle();
-    let server_task = tokio::spawn(server);
+    if let Some(keep_alive) = server_config.keep_alive {
+        server = server.keep_alive(keep_alive);
+    }
+    if let Some(max_connections) = server_config.max_connections {
+        server = server.max_connections(max_connections);
+    }
+    if let Some(client_request_timeout) = server_config.client_request_timeout {
+        server = server.client_request_timeout(client_request_timeout);
+    }
 
-    let mut centrifugo =
-        Command::new(std::fs::canonicalize("centrifugo").expect("centrifugo not found"))
-            .spawn()
-            .expect("Failed to spawn child process");
+    let server = server
+        .bind_rustls_0_22(bind_address, tls_config)
+        .expect("bind_rustls")
+        .disable_signals()
+        .run();
 
-    debug!("centrifugo pid: {}", centrifugo.id().unwrap());
+    let server_handle = server.handle();
+    let server_task = tokio::spawn(server);
 
+    // centrifugo is killed when its supervisor is dropped on shutdown of the runtime
     tokio::select! {
         _ = tokio::signal::ctrl_c() => {
             debug!("ctrl-c");
@@ -106,60 +303,398 @@ async fn main() {
         },
         _ = server_task => {
             debug!("server stopped");
-            centrifugo.kill().await.expect("kill centrifugo failed");
-            debug!("centrifugo killed");
         },
-        _ = centrifugo.wait() => {
-            debug!("centrifugo stopped");
-            server_handle.stop(true).await;
-            debug!("server stopped");
-        }
     }
 
     debug!("good bye");
 }
 
-async fn index() -> actix_web::Result<NamedFile> {
+/// centrifugo only provides live updates, so omnect-ui keeps serving the UI
+/// while centrifugo is in a crash loop, reports it via /healthcheck and
+/// starts it again at a slow pace.
+async fn run_centrifugo(centrifugo: Arc<Centrifugo>, path: PathBuf) {
+    loop {
+        let probe = centrifugo.clone();
+
+        let result = ProcessSupervisor::new("centrifugo", path.clone())
+            .restart_policy(RestartPolicy::Always)
+            .max_restarts(CENTRIFUGO_MAX_RESTARTS)
+            .health_probe(CENTRIFUGO_PROBE_INTERVAL, move || {
+                let centrifugo = probe.clone();
+                async move {
+                    let healthy = centrifugo.probe().await;
+
+                    if healthy && centrifugo.failed() {
+                        info!("centrifugo recovered");
+                        centrifugo.set_failed(false);
+                    }
+
+                    healthy
+                }
+            })
+            .run()
+            .await;
+
+        match result {
+            Ok(status) => error!("centrifugo stopped: {status}"),
+            Err(e) => error!("centrifugo failed: {e:#}"),
+        }
+
+        centrifugo.set_failed(true);
+
+        warn!("start centrifugo again in {CENTRIFUGO_RETRY_DELAY:?}");
+
+        tokio::time::sleep(CENTRIFUGO_RETRY_DELAY).await;
+    }
+}
+
+/// Re-reads the config on SIGHUP and applies the settings that can be changed
+/// at runtime. All other changes are reported as requiring a restart.
+async fn reload_config(
+    cli: Cli,
+    config: AppConfig,
+    device_service_client: Arc<OmnectDeviceServiceClient>,
+    request_metrics: Arc<RequestMetrics>,
+    command_runner: Arc<CommandRunner>,
+    login_lockout: Arc<LoginLockout>,
+) {
+    let mut sighup = signal(SignalKind::hangup()).expect("cannot install SIGHUP handler");
+
+    while sighup.recv().await.is_some() {
+        info!("SIGHUP: reload config");
+
+        let new_config = match AppConfig::load(&cli) {
+            Ok(new_config) => new_config,
+            Err(e) => {
+                error!("reload config failed, keep current config: {e:#}");
+                continue;
+            }
+        };
+
+        let restart_required = config.changes_requiring_restart(&new_config);
+
+        if !restart_required.is_empty() {
+            warn!(
+                "reload config: changes of {} require a restart",
+                restart_required.join(", ")
+            );
+        }
+
+        logging::set_filter(new_config.log_level.as_deref());
+        device_service_client.reconfigure(&new_config.device_service);
+        request_metrics.set_slow_threshold(new_config.slow_request_threshold);
+        command_runner.reconfigure(&new_config.commands);
+        login_lockout.reconfigure(new_config.max_failed_logins, new_config.login_lockout);
+
+        debug!("reloaded config: {new_config:?}");
+    }
+}
+
+async fn index(
+    device_service_client: web::Data<OmnectDeviceServiceClient>,
+) -> actix_web::Result<NamedFile> {
     debug!("index() called");
 
-    // trigger omnect-device-service to republish
-    match post("/republish/v1", None).await {
-        Ok(response) => response,
-        Err(e) => {
+    // trigger omnect-device-service to republish in the background, the SPA is
+    // served right away in order to show the device service state
+    let device_service_client = device_service_client.into_inner();
+
+    actix_rt::spawn(async move {
+        if let Err(e) = device_service_client.republish().await {
             error!("republish failed: {e}");
-            return Err(actix_web::error::ErrorInternalServerError(
-                "republish failed",
-            ));
         }
-    };
+    });
 
     Ok(NamedFile::open(
         std::fs::canonicalize("static/index.html").expect("static/index.html not found"),
     )?)
 }
 
-async fn login_token(auth: BasicAuth) -> impl Responder {
-    debug!("login_token() called");
+async fn healthcheck(
+    device_service_client: web::Data<OmnectDeviceServiceClient>,
+    centrifugo: web::Data<Centrifugo>,
+) -> impl Responder {
+    debug!("healthcheck() called");
 
-    match verify_user(auth) {
-        Ok(true) => token(),
-        Ok(false) => {
-            error!("login_token verify false");
-            HttpResponse::build(StatusCode::UNAUTHORIZED).finish()
+    if centrifugo.failed() {
+        return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE)
+            .body("centrifugo failed, live updates unavailable");
+    }
+
+    if !device_service_client.reachable() {
+        return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE)
+            .body("device service unreachable");
+    }
+
+    if device_service_client.degraded() {
+        return HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE)
+            .body("device service degraded");
+    }
+
+    HttpResponse::Ok().finish()
+}
+
+/// liveness: the process is up and serving requests
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().finish()
+}
+
+/// readiness: all services omnect-ui depends on are available
+async fn readyz(
+    device_service_client: web::Data<OmnectDeviceServiceClient>,
+    centrifugo: web::Data<Centrifugo>,
+) -> impl Responder {
+    debug!("readyz() called");
+
+    let device_service = device_service_client.reachable() && !device_service_client.degraded();
+    let centrifugo = centrifugo.probe().await;
+
+    let status_code = if device_service && centrifugo {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    HttpResponse::build(status_code).json(json!({
+        "device-service": device_service,
+        "centrifugo": centrifugo,
+    }))
+}
+
+async fn version(
+    auth: BearerAuth,
+    token_manager: web::Data<TokenManager>,
+    version_info: web::Data<VersionInfo>,
+) -> impl Responder {
+    debug!("version() called");
+
+    if let Err(response) = authorize(&token_manager, auth, "version") {
+        return response;
+    }
+
+    HttpResponse::Ok().json(version_info.as_ref())
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum CertificateFormat {
+    #[default]
+    Pem,
+    Der,
+}
+
+#[derive(Deserialize)]
+struct CertificateQuery {
+    #[serde(default)]
+    format: CertificateFormat,
+}
+
+/// certificates are public, so no authorization is required to establish trust before login
+async fn get_certificate_chain(
+    query: web::Query<CertificateQuery>,
+    certificate_chain: web::Data<CertificateChain>,
+) -> impl Responder {
+    debug!("get_certificate_chain() called");
+
+    match query.format {
+        CertificateFormat::Pem => HttpResponse::Ok()
+            .content_type("application/x-pem-file")
+            .insert_header((
+                "Content-Disposition",
+                "attachment; filename=\"ca-chain.pem\"",
+            ))
+            .body(certificate_chain.pem()),
+        CertificateFormat::Der => match certificate_chain.der() {
+            Some(der) => HttpResponse::Ok()
+                .content_type("application/pkix-cert")
+                .insert_header(("Content-Disposition", "attachment; filename=\"ca.cer\""))
+                .body(der.to_vec()),
+            None => HttpResponse::NotFound().finish(),
+        },
+    }
+}
+
+async fn latency_metrics(
+    auth: BearerAuth,
+    token_manager: web::Data<TokenManager>,
+    request_metrics: web::Data<RequestMetrics>,
+) -> impl Responder {
+    debug!("latency_metrics() called");
+
+    if let Err(response) = authorize(&token_manager, auth, "latency-metrics") {
+        return response;
+    }
+
+    HttpResponse::Ok().json(request_metrics.snapshot())
+}
+
+async fn system_os(auth: BearerAuth, token_manager: web::Data<TokenManager>) -> impl Responder {
+    debug!("system_os() called");
+
+    if let Err(response) = authorize(&token_manager, auth, "system-os") {
+        return response;
+    }
+
+    match OsDetails::read() {
+        Ok(details) => HttpResponse::Ok().json(details),
+        Err(e) => {
+            error!("system-os: {e:#}");
+            HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish()
         }
+    }
+}
+
+#[derive(Deserialize)]
+struct AuditLogQuery {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+}
+
+async fn get_audit_log(
+    auth: BearerAuth,
+    query: web::Query<AuditLogQuery>,
+    token_manager: web::Data<TokenManager>,
+    audit_log: web::Data<AuditLog>,
+) -> impl Responder {
+    debug!("get_audit_log() called");
+
+    if let Err(response) = authorize(&token_manager, auth, "audit-log") {
+        return response;
+    }
+
+    let limit = query
+        .limit
+        .unwrap_or(audit_log::DEFAULT_PAGE_SIZE)
+        .min(audit_log::MAX_PAGE_SIZE);
+
+    HttpResponse::Ok().json(audit_log.page(query.offset, limit))
+}
+
+#[derive(Deserialize)]
+struct TelemetryQuery {
+    hours: Option<u64>,
+}
+
+async fn get_telemetry_history(
+    auth: BearerAuth,
+    query: web::Query<TelemetryQuery>,
+    token_manager: web::Data<TokenManager>,
+    telemetry_history: web::Data<TelemetryHistory>,
+) -> impl Responder {
+    debug!("get_telemetry_history() called");
+
+    if let Err(response) = authorize(&token_manager, auth, "telemetry-history") {
+        return response;
+    }
+
+    let hours = query
+        .hours
+        .unwrap_or(telemetry::MAX_HISTORY_HOURS)
+        .min(telemetry::MAX_HISTORY_HOURS);
+
+    HttpResponse::Ok().json(telemetry_history.history(hours))
+}
+
+async fn get_device_identity(
+    auth: BearerAuth,
+    token_manager: web::Data<TokenManager>,
+    device_identity: web::Data<DeviceIdentityStore>,
+) -> impl Responder {
+    debug!("get_device_identity() called");
+
+    if let Err(response) = authorize(&token_manager, auth, "get-device-identity") {
+        return response;
+    }
+
+    HttpResponse::Ok().json(device_identity.get())
+}
+
+async fn set_device_identity(
+    auth: BearerAuth,
+    body: web::Json<DeviceIdentity>,
+    token_manager: web::Data<TokenManager>,
+    device_identity: web::Data<DeviceIdentityStore>,
+) -> impl Responder {
+    debug!("set_device_identity() called");
+
+    if let Err(response) = authorize(&token_manager, auth, "set-device-identity") {
+        return response;
+    }
+
+    let identity = body.into_inner();
+
+    if let Err(e) = identity.validate() {
+        return HttpResponse::BadRequest().body(e.to_string());
+    }
+
+    match device_identity.set(identity).await {
+        Ok(()) => HttpResponse::Ok().finish(),
         Err(e) => {
-            error!("login_token: {e}");
+            error!("set-device-identity: {e:#}");
             HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish()
         }
     }
 }
 
-async fn refresh_token(auth: BearerAuth) -> impl Responder {
+#[derive(Deserialize)]
+struct LoginQuery {
+    #[serde(default)]
+    remember: bool,
+}
+
+async fn login_token(
+    req: HttpRequest,
+    auth: BasicAuth,
+    query: web::Query<LoginQuery>,
+    login_attempts: web::Data<LoginAttempts>,
+    login_lockout: web::Data<LoginLockout>,
+    token_manager: web::Data<TokenManager>,
+) -> impl Responder {
+    debug!("login_token() called");
+
+    let peer = req
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or("unknown".to_string());
+    let user = auth.user_id().to_string();
+
+    // credentials are not even verified while locked out, so guessing gains nothing
+    if let Err(retry_after) = login_lockout.check(&peer) {
+        warn!("login_token: {peer} locked out");
+        login_attempts.record(&peer, &user, LoginOutcome::LockedOut);
+        return HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after.as_secs().max(1).to_string()))
+            .finish();
+    }
+
+    if verify_user(&auth) {
+        login_lockout.record_success(&peer);
+        login_attempts.record(&peer, &user, LoginOutcome::Success);
+        token(&token_manager, query.remember)
+    } else {
+        error!("login_token verify false");
+        login_attempts.record(&peer, &user, LoginOutcome::Failure);
+        HttpResponse::build(StatusCode::UNAUTHORIZED).finish()
+    }
+}
+
+async fn refresh_token(auth: BearerAuth, token_manager: web::Data<TokenManager>) -> impl Responder {
     debug!("refresh_token() called");
 
-    match verify_token(auth) {
-        Ok(true) => token(),
-        Ok(false) => {
+    match token_manager.verify_token(auth.token()) {
+        Ok(Some(session)) => match token_manager.refresh_token(&session) {
+            Ok(Some(token)) => HttpResponse::Ok().body(token),
+            Ok(None) => {
+                info!("refresh_token: session reached its max age");
+                HttpResponse::build(StatusCode::UNAUTHORIZED).finish()
+            }
+            Err(e) => {
+                error!("refresh_token: {e:#}");
+                HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish()
+            }
+        },
+        Ok(None) => {
             error!("refresh_token verify false");
             HttpResponse::build(StatusCode::UNAUTHORIZED).finish()
         }
@@ -170,118 +705,169 @@ async fn refresh_token(auth: BearerAuth) -> impl Responder {
     }
 }
 
-async fn reboot(auth: BearerAuth) -> impl Responder {
-    debug!("reboot() called");
+async fn logout_all(
+    auth: BearerAuth,
+    token_manager: web::Data<TokenManager>,
+    centrifugo: web::Data<Centrifugo>,
+) -> impl Responder {
+    debug!("logout_all() called");
 
-    match post("/reboot/v1", Some(auth)).await {
-        Ok(response) => response,
-        Err(e) => {
-            error!("reboot failed: {e}");
-            HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish()
-        }
+    if let Err(response) = authorize(&token_manager, auth, "logout-all") {
+        return response;
     }
-}
 
-async fn reload_network(auth: BearerAuth) -> impl Responder {
-    debug!("reload_network() called");
+    // a revocation that cannot be persisted would be lifted by a restart
+    let revoked = token_manager.revoke_all();
 
-    match post("/reload-network/v1", Some(auth)).await {
-        Ok(response) => response,
-        Err(e) => {
-            error!("reload-network failed: {e}");
-            HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish()
-        }
+    if let Err(e) = &revoked {
+        error!("logout-all: {e:#}");
     }
-}
 
-async fn post(path: &str, auth: Option<BearerAuth>) -> Result<HttpResponse> {
-    if let Some(auth) = auth {
-        if !verify_token(auth)? {
-            error!("post {path} verify false");
-            return Ok(HttpResponse::build(StatusCode::UNAUTHORIZED).finish());
-        }
+    // tokens are verified by centrifugo only on connect, so open connections are closed explicitly
+    if let Err(e) = centrifugo.disconnect(token_manager::TOKEN_SUBJECT).await {
+        error!("logout-all: {e:#}");
     }
 
-    let stream = UnixStream::connect(std::env::var("SOCKET_PATH").expect("SOCKET_PATH missing"))
-        .await
-        .context("cannot create unix stream")?;
+    if revoked.is_err() {
+        return HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish();
+    }
 
-    let (mut sender, conn) = http1::handshake(TokioIo::new(stream))
-        .await
-        .context("unix stream handshake failed")?;
+    info!("all sessions revoked");
 
-    actix_rt::spawn(async move {
-        if let Err(err) = conn.await {
-            error!("post connection failed: {:?}", err);
-        }
-    });
+    HttpResponse::Ok().finish()
+}
 
-    sender
-        .ready()
-        .await
-        .context("unix stream unexpectedly closed")?;
+async fn reboot(
+    req: HttpRequest,
+    auth: BearerAuth,
+    token_manager: web::Data<TokenManager>,
+    idempotency_cache: web::Data<IdempotencyCache>,
+    device_service_client: web::Data<OmnectDeviceServiceClient>,
+) -> impl Responder {
+    debug!("reboot() called");
 
-    let request = Request::builder()
-        .uri(path)
-        .method("POST")
-        .header("Host", "localhost")
-        .body(Empty::<Bytes>::new())
-        .context("build request failed")?;
+    if let Err(response) = authorize(&token_manager, auth, "reboot") {
+        return response;
+    }
 
-    let res = sender
-        .send_request(request)
+    idempotency_cache
+        .run(&req, "reboot", || async {
+            match device_service_client.reboot().await {
+                Ok(response) => response,
+                Err(e) => {
+                    error!("reboot failed: {e}");
+                    e.error_response()
+                }
+            }
+        })
         .await
-        .context("send request failed")?;
+}
+
+async fn reload_network(
+    req: HttpRequest,
+    auth: BearerAuth,
+    token_manager: web::Data<TokenManager>,
+    idempotency_cache: web::Data<IdempotencyCache>,
+    device_service_client: web::Data<OmnectDeviceServiceClient>,
+) -> impl Responder {
+    debug!("reload_network() called");
 
-    let status_code =
-        StatusCode::from_u16(res.status().as_u16()).context("get status code failed")?;
+    if let Err(response) = authorize(&token_manager, auth, "reload-network") {
+        return response;
+    }
 
-    let body = res
-        .collect()
+    idempotency_cache
+        .run(&req, "reload-network", || async {
+            match device_service_client.reload_network().await {
+                Ok(response) => response,
+                Err(e) => {
+                    error!("reload-network failed: {e}");
+                    e.error_response()
+                }
+            }
+        })
         .await
-        .context("collect response body failed")?;
+}
 
-    let body = String::from_utf8(body.to_bytes().to_vec()).context("get response body failed")?;
+async fn run_command(
+    req: HttpRequest,
+    auth: BearerAuth,
+    name: web::Path<String>,
+    body: web::Json<CommandRequest>,
+    token_manager: web::Data<TokenManager>,
+    command_runner: web::Data<CommandRunner>,
+) -> impl Responder {
+    debug!("run_command() called");
+
+    if let Err(response) = authorize(&token_manager, auth, "run-command") {
+        return response;
+    }
 
-    Ok(HttpResponse::build(status_code).body(body))
-}
+    let peer = req
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or("unknown".to_string());
 
-fn token() -> HttpResponse {
-    if let Ok(key) = std::env::var("CENTRIFUGO_TOKEN_HMAC_SECRET_KEY") {
-        let key = HS256Key::from_bytes(key.as_bytes());
-        let claims =
-            Claims::create(Duration::from_hours(TOKEN_EXPIRE_HOURES)).with_subject("omnect-ui");
+    match command_runner.run(&name, body.into_inner(), &peer).await {
+        Ok(output) => HttpResponse::Ok().json(output),
+        Err(e) => {
+            error!("run-command failed: {e}");
+            e.error_response()
+        }
+    }
+}
 
-        if let Ok(token) = key.authenticate(claims) {
-            return HttpResponse::Ok().body(token);
-        } else {
-            error!("token: cannot create token");
-        };
-    } else {
-        error!("token: missing secret key");
-    };
+fn authorize(
+    token_manager: &TokenManager,
+    auth: BearerAuth,
+    caller: &str,
+) -> Result<(), HttpResponse> {
+    match token_manager.verify_token(auth.token()) {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => {
+            error!("{caller} verify false");
+            Err(HttpResponse::build(StatusCode::UNAUTHORIZED).finish())
+        }
+        Err(e) => {
+            error!("{caller}: {e}");
+            Err(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish())
+        }
+    }
+}
 
-    HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish()
+fn token(token_manager: &TokenManager, remember: bool) -> HttpResponse {
+    match token_manager.create_token(remember) {
+        Ok(token) => HttpResponse::Ok().body(token),
+        Err(e) => {
+            error!("token: {e:#}");
+            HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).finish()
+        }
+    }
 }
 
-fn verify_token(auth: BearerAuth) -> Result<bool> {
-    let key = std::env::var("CENTRIFUGO_TOKEN_HMAC_SECRET_KEY").context("missing jwt secret")?;
-    let key = HS256Key::from_bytes(key.as_bytes());
-    let options = VerificationOptions {
-        accept_future: true,
-        time_tolerance: Some(Duration::from_mins(15)),
-        max_validity: Some(Duration::from_hours(TOKEN_EXPIRE_HOURES)),
-        required_subject: Some("omnect-ui".to_string()),
-        ..Default::default()
+/// Credentials are compared by their digests in constant time, so the response
+/// time reveals neither matching prefixes nor the length of the configured
+/// credentials. Missing credentials fail like wrong ones, after the same work.
+fn verify_user(auth: &BasicAuth) -> bool {
+    let credentials = match (std::env::var("LOGIN_USER"), std::env::var("LOGIN_PASSWORD")) {
+        (Ok(user), Ok(password)) => Some((user, Zeroizing::new(password))),
+        _ => {
+            error!("verify_user: missing LOGIN_USER or LOGIN_PASSWORD");
+            None
+        }
     };
 
-    Ok(key
-        .verify_token::<NoCustomClaims>(auth.token(), Some(options))
-        .is_ok())
+    let (user, password) = credentials
+        .as_ref()
+        .map(|(user, password)| (user.as_str(), password.as_str()))
+        .unwrap_or_default();
+
+    let user_matches = digest(auth.user_id()).ct_eq(&digest(user));
+    let password_matches = digest(auth.password().unwrap_or_default()).ct_eq(&digest(password));
+
+    credentials.is_some() && bool::from(user_matches & password_matches)
 }
 
-fn verify_user(auth: BasicAuth) -> Result<bool> {
-    let user = std::env::var("LOGIN_USER").context("login_token: missing user")?;
-    let password = std::env::var("LOGIN_PASSWORD").context("login_token: missing password")?;
-    Ok(auth.user_id() == user && auth.password() == Some(&password))
+fn digest(value: &str) -> [u8; 32] {
+    Sha256::digest(value.as_bytes()).into()
 }
@@ -0,0 +1,529 @@
+use crate::{
+    centrifugo::Centrifugo,
+    config::DeviceServiceConfig,
+    operation_lock::{Operation, OperationLock},
+    problem_details::problem_response,
+    simulation::Simulation,
+};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use anyhow::{bail, Context, Result};
+use http_body_util::{BodyExt, Empty};
+use hyper::{
+    Request, Uri,
+    {body::Bytes, client::conn::http1},
+};
+use hyper_util::rt::TokioIo;
+use log::{debug, error, info, warn};
+use serde_json::json;
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, UnixStream},
+};
+
+const RETRY_BACKOFF_BASE: Duration = Duration::from_millis(200);
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+const CIRCUIT_FAILURE_THRESHOLD: u32 = 5;
+const CIRCUIT_COOLDOWN: Duration = Duration::from_secs(30);
+/// centrifugo keeps the last publication of every channel in its history,
+/// so a recent republish does not need to be repeated on every page load
+const REPUBLISH_TTL: Duration = Duration::from_secs(10);
+/// further destructive operations are rejected while the device reboots, unless
+/// the device service is reachable again before
+const REBOOT_COOLDOWN: Duration = Duration::from_secs(300);
+/// further destructive operations are rejected while the network comes up again
+const RELOAD_NETWORK_COOLDOWN: Duration = Duration::from_secs(30);
+const REACHABILITY_PROBE_INTERVAL: Duration = Duration::from_secs(5);
+const DEVICE_SERVICE_STATUS_CHANNEL: &str = "DeviceServiceStatus";
+
+/// Errors of omnect-device-service requests, mapped to the HTTP status returned to the client.
+#[derive(Debug, thiserror::Error)]
+pub enum OdsError {
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("device service busy: {0}")]
+    Busy(String),
+    #[error("{0} in progress")]
+    OperationInProgress(Operation),
+    #[error("validation failed: {0}")]
+    Validation(String),
+    #[error("device service unavailable: {0}")]
+    Unavailable(String),
+    #[error("unexpected device service response ({0}): {1}")]
+    Unexpected(StatusCode, String),
+}
+
+impl OdsError {
+    fn from_response(status_code: StatusCode, body: String) -> Self {
+        match status_code {
+            StatusCode::NOT_FOUND => OdsError::NotFound(body),
+            StatusCode::CONFLICT | StatusCode::LOCKED | StatusCode::TOO_MANY_REQUESTS => {
+                OdsError::Busy(body)
+            }
+            StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => {
+                OdsError::Validation(body)
+            }
+            StatusCode::SERVICE_UNAVAILABLE => OdsError::Unavailable(body),
+            _ => OdsError::Unexpected(status_code, body),
+        }
+    }
+}
+
+impl ResponseError for OdsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            OdsError::NotFound(_) => StatusCode::NOT_FOUND,
+            OdsError::Busy(_) | OdsError::OperationInProgress(_) => StatusCode::CONFLICT,
+            OdsError::Validation(_) => StatusCode::BAD_REQUEST,
+            OdsError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            OdsError::Unexpected(..) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        problem_response(self.status_code(), &self.to_string())
+    }
+}
+
+/// Only idempotent requests are retried, e.g. a reboot must not be sent twice.
+#[derive(Clone, Copy, PartialEq)]
+enum Retry {
+    Idempotent,
+    Once,
+}
+
+/// omnect-device-service is usually reached via its unix socket. For development
+/// and split deployments it can also be reached via plain http or be simulated.
+/// https is not supported, so http should only be used on trusted networks.
+enum Endpoint {
+    Unix(PathBuf),
+    Http(String),
+    Simulated(Simulation),
+}
+
+impl Endpoint {
+    fn from_config(config: &DeviceServiceConfig) -> Result<Self> {
+        if let Some(url) = &config.url {
+            let uri = url.parse::<Uri>().context("DEVICE_SERVICE_URL format")?;
+
+            if uri.scheme_str() != Some("http") {
+                bail!("DEVICE_SERVICE_URL: only http is supported");
+            }
+
+            // request paths are fixed, a base path would silently be ignored
+            if !matches!(uri.path(), "" | "/") || uri.query().is_some() {
+                bail!("DEVICE_SERVICE_URL: must not contain a path, expected http://host:port");
+            }
+
+            let Some(authority) = uri.authority() else {
+                bail!("DEVICE_SERVICE_URL: missing host");
+            };
+
+            let port = authority.port_u16().unwrap_or(80);
+
+            return Ok(Endpoint::Http(format!("{}:{port}", authority.host())));
+        }
+
+        let Some(socket_path) = &config.socket_path else {
+            bail!("either SOCKET_PATH or DEVICE_SERVICE_URL must be set");
+        };
+
+        Ok(Endpoint::Unix(socket_path.clone()))
+    }
+
+    async fn probe(&self) -> bool {
+        match self {
+            Endpoint::Unix(path) => UnixStream::connect(path).await.is_ok(),
+            Endpoint::Http(address) => TcpStream::connect(address).await.is_ok(),
+            Endpoint::Simulated(simulation) => simulation.reachable(),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+            Endpoint::Http(address) => write!(f, "http://{address}"),
+            Endpoint::Simulated(_) => write!(f, "simulation"),
+        }
+    }
+}
+
+pub struct OmnectDeviceServiceClient {
+    endpoint: Endpoint,
+    timeout_millis: AtomicU64,
+    retries: AtomicU32,
+    circuit: Mutex<CircuitBreaker>,
+    last_republish: Mutex<Option<Instant>>,
+    reachable: AtomicBool,
+    operation_lock: OperationLock,
+}
+
+impl OmnectDeviceServiceClient {
+    pub fn new(config: &DeviceServiceConfig) -> Result<Self> {
+        Ok(Self::with_endpoint(Endpoint::from_config(config)?, config))
+    }
+
+    pub fn simulated(simulation: Simulation, config: &DeviceServiceConfig) -> Self {
+        Self::with_endpoint(Endpoint::Simulated(simulation), config)
+    }
+
+    fn with_endpoint(endpoint: Endpoint, config: &DeviceServiceConfig) -> Self {
+        OmnectDeviceServiceClient {
+            endpoint,
+            timeout_millis: AtomicU64::new(config.timeout.as_millis() as u64),
+            retries: AtomicU32::new(config.retries),
+            circuit: Mutex::new(CircuitBreaker::default()),
+            last_republish: Mutex::new(None),
+            reachable: AtomicBool::new(true),
+            operation_lock: OperationLock::default(),
+        }
+    }
+
+    /// applies the reloadable settings of a changed config
+    pub fn reconfigure(&self, config: &DeviceServiceConfig) {
+        self.timeout_millis
+            .store(config.timeout.as_millis() as u64, Ordering::Relaxed);
+        self.retries.store(config.retries, Ordering::Relaxed);
+    }
+
+    /// false while the endpoint cannot be connected, see [`watch_reachability`]
+    pub fn reachable(&self) -> bool {
+        self.reachable.load(Ordering::Relaxed)
+    }
+
+    /// true as long as the circuit breaker rejects requests
+    pub fn degraded(&self) -> bool {
+        self.circuit.lock().unwrap().is_open()
+    }
+
+    pub async fn republish(&self) -> Result<(), OdsError> {
+        if let Some(last) = *self.last_republish.lock().unwrap() {
+            if last.elapsed() < REPUBLISH_TTL {
+                debug!("republish skipped, last one {:?} ago", last.elapsed());
+                return Ok(());
+            }
+        }
+
+        self.post("/republish/v1", Retry::Idempotent).await?;
+
+        *self.last_republish.lock().unwrap() = Some(Instant::now());
+
+        Ok(())
+    }
+
+    pub async fn reboot(&self) -> Result<HttpResponse, OdsError> {
+        let guard = self
+            .operation_lock
+            .try_acquire(Operation::Reboot)
+            .map_err(OdsError::OperationInProgress)?;
+
+        self.invalidate();
+
+        let response = self.post("/reboot/v1", Retry::Once).await?;
+
+        // the device is going down, nothing else must be started until it is back
+        guard.keep(REBOOT_COOLDOWN);
+
+        Ok(response)
+    }
+
+    pub async fn reload_network(&self) -> Result<HttpResponse, OdsError> {
+        let guard = self
+            .operation_lock
+            .try_acquire(Operation::ReloadNetwork)
+            .map_err(OdsError::OperationInProgress)?;
+
+        self.invalidate();
+
+        let response = self.post("/reload-network/v1", Retry::Once).await?;
+
+        guard.keep(RELOAD_NETWORK_COOLDOWN);
+
+        Ok(response)
+    }
+
+    /// forces the next republish, since the device state is about to change
+    fn invalidate(&self) {
+        *self.last_republish.lock().unwrap() = None;
+    }
+
+    async fn post(&self, path: &str, retry: Retry) -> Result<HttpResponse, OdsError> {
+        if !self.reachable() {
+            return Err(OdsError::Unavailable(
+                "device service unreachable".to_string(),
+            ));
+        }
+
+        if !self.circuit.lock().unwrap().allow() {
+            return Err(OdsError::Unavailable("device service degraded".to_string()));
+        }
+
+        let timeout = Duration::from_millis(self.timeout_millis.load(Ordering::Relaxed));
+        let attempts = if retry == Retry::Idempotent {
+            self.retries.load(Ordering::Relaxed).saturating_add(1)
+        } else {
+            1
+        };
+
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+
+            let result = match tokio::time::timeout(timeout, self.send(path)).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!("timeout after {timeout:?}")),
+            };
+
+            match result {
+                Ok((status_code, body)) => {
+                    self.circuit.lock().unwrap().succeeded();
+
+                    if status_code.is_success() {
+                        return Ok(HttpResponse::build(status_code).body(body));
+                    }
+
+                    return Err(OdsError::from_response(status_code, body));
+                }
+                Err(e) if attempt < attempts => {
+                    let backoff = (RETRY_BACKOFF_BASE * 2u32.pow((attempt - 1).min(16)))
+                        .min(MAX_RETRY_BACKOFF);
+                    warn!("post {path} failed (attempt {attempt}/{attempts}), retry in {backoff:?}: {e:#}");
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => {
+                    self.circuit.lock().unwrap().failed();
+                    return Err(OdsError::Unavailable(format!("post {path} failed: {e:#}")));
+                }
+            }
+        }
+    }
+
+    async fn send(&self, path: &str) -> Result<(StatusCode, String)> {
+        match &self.endpoint {
+            Endpoint::Unix(socket_path) => {
+                let stream = UnixStream::connect(socket_path)
+                    .await
+                    .context("cannot create unix stream")?;
+
+                send_request(stream, "localhost", path).await
+            }
+            Endpoint::Http(address) => {
+                let stream = TcpStream::connect(address)
+                    .await
+                    .context("cannot create tcp stream")?;
+
+                send_request(stream, address, path).await
+            }
+            Endpoint::Simulated(simulation) => simulation.post(path).await,
+        }
+    }
+}
+
+async fn send_request<S>(stream: S, host: &str, path: &str) -> Result<(StatusCode, String)>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) = http1::handshake(TokioIo::new(stream))
+        .await
+        .context("handshake failed")?;
+
+    tokio::spawn(async move {
+        if let Err(err) = conn.await {
+            error!("post connection failed: {:?}", err);
+        }
+    });
+
+    sender
+        .ready()
+        .await
+        .context("connection unexpectedly closed")?;
+
+    let request = Request::builder()
+        .uri(path)
+        .method("POST")
+        .header("Host", host)
+        .body(Empty::<Bytes>::new())
+        .context("build request failed")?;
+
+    let res = sender
+        .send_request(request)
+        .await
+        .context("send request failed")?;
+
+    let status_code =
+        StatusCode::from_u16(res.status().as_u16()).context("get status code failed")?;
+
+    let body = res
+        .collect()
+        .await
+        .context("collect response body failed")?;
+
+    let body = String::from_utf8(body.to_bytes().to_vec()).context("get response body failed")?;
+
+    Ok((status_code, body))
+}
+
+/// Probes the device service endpoint in the background, so that omnect-ui keeps
+/// serving the SPA while omnect-device-service is missing and enables the
+/// endpoints again as soon as it appears. Changes of the device service state
+/// ("ok", "unreachable" or "degraded") are published to centrifugo.
+pub async fn watch_reachability(
+    client: Arc<OmnectDeviceServiceClient>,
+    centrifugo: Arc<Centrifugo>,
+) {
+    let mut interval = tokio::time::interval(REACHABILITY_PROBE_INTERVAL);
+    let mut published = None;
+
+    loop {
+        interval.tick().await;
+
+        let reachable = client.endpoint.probe().await;
+
+        match (
+            client.reachable.swap(reachable, Ordering::Relaxed),
+            reachable,
+        ) {
+            (true, false) => warn!("device service unreachable: {}", client.endpoint),
+            (false, true) => {
+                info!("device service reachable again");
+                client.circuit.lock().unwrap().succeeded();
+                client.operation_lock.release_kept();
+                client.invalidate();
+
+                if let Err(e) = client.republish().await {
+                    error!("republish after reconnect failed: {e}");
+                }
+            }
+            _ => {}
+        }
+
+        let status = if !client.reachable() {
+            "unreachable"
+        } else if client.degraded() {
+            "degraded"
+        } else {
+            "ok"
+        };
+
+        if published == Some(status) {
+            continue;
+        }
+
+        match centrifugo
+            .publish(DEVICE_SERVICE_STATUS_CHANNEL, json!({ "status": status }))
+            .await
+        {
+            Ok(()) => published = Some(status),
+            Err(e) => debug!("publish device service status failed: {e:#}"),
+        }
+    }
+}
+
+/// Stops hammering omnect-device-service after repeated failures. Once the cooldown
+/// elapsed a single request is let through again (half-open) to probe the service.
+#[derive(Default)]
+struct CircuitBreaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn is_open(&self) -> bool {
+        self.open_until.is_some()
+    }
+
+    fn allow(&mut self) -> bool {
+        match self.open_until {
+            Some(until) if Instant::now() < until => false,
+            Some(_) => {
+                // half-open: let one request pass, a further failure re-opens immediately
+                self.open_until = Some(Instant::now() + CIRCUIT_COOLDOWN);
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn succeeded(&mut self) {
+        if self.is_open() {
+            info!("device service recovered");
+        }
+
+        self.failures = 0;
+        self.open_until = None;
+    }
+
+    fn failed(&mut self) {
+        self.failures += 1;
+
+        if CIRCUIT_FAILURE_THRESHOLD <= self.failures {
+            if !self.is_open() {
+                warn!("device service degraded after {} failures", self.failures);
+            }
+
+            self.open_until = Some(Instant::now() + CIRCUIT_COOLDOWN);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn circuit_opens_after_threshold() {
+        let mut circuit = CircuitBreaker::default();
+
+        for _ in 1..CIRCUIT_FAILURE_THRESHOLD {
+            circuit.failed();
+            assert!(circuit.allow());
+        }
+
+        circuit.failed();
+        assert!(circuit.is_open());
+        assert!(!circuit.allow());
+    }
+
+    #[test]
+    fn circuit_closes_on_success() {
+        let mut circuit = CircuitBreaker::default();
+
+        for _ in 0..CIRCUIT_FAILURE_THRESHOLD {
+            circuit.failed();
+        }
+
+        circuit.succeeded();
+        assert!(!circuit.is_open());
+        assert!(circuit.allow());
+        assert_eq!(circuit.failures, 0);
+    }
+
+    #[test]
+    fn circuit_half_open_after_cooldown() {
+        let mut circuit = CircuitBreaker::default();
+
+        for _ in 0..CIRCUIT_FAILURE_THRESHOLD {
+            circuit.failed();
+        }
+
+        circuit.open_until = Some(Instant::now() - Duration::from_secs(1));
+
+        // a single probe passes, further requests wait for its outcome
+        assert!(circuit.allow());
+        assert!(!circuit.allow());
+
+        circuit.failed();
+        assert!(!circuit.allow());
+    }
+}
@@ -0,0 +1,153 @@
+use log::info;
+use std::{
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Operations that change the device state and must not overlap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Reboot,
+    ReloadNetwork,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Reboot => write!(f, "reboot"),
+            Operation::ReloadNetwork => write!(f, "reload-network"),
+        }
+    }
+}
+
+/// Allows only one destructive operation at a time, e.g. no reboot while
+/// the network is being reloaded.
+#[derive(Default)]
+pub struct OperationLock {
+    /// running operation, kept until the given instant after its request if set
+    running: Mutex<Option<(Operation, Option<Instant>)>>,
+}
+
+impl OperationLock {
+    /// returns the currently running operation if the lock is already taken
+    pub fn try_acquire(&self, operation: Operation) -> Result<OperationGuard<'_>, Operation> {
+        let mut running = self.running.lock().unwrap();
+
+        match *running {
+            Some((_, Some(until))) if until <= Instant::now() => {}
+            Some((running, _)) => return Err(running),
+            None => {}
+        }
+
+        *running = Some((operation, None));
+
+        Ok(OperationGuard { lock: self })
+    }
+
+    /// releases an operation kept after its request before its time, e.g. as
+    /// soon as the device is back after a reboot
+    pub fn release_kept(&self) {
+        let mut running = self.running.lock().unwrap();
+
+        if let Some((operation, Some(_))) = *running {
+            info!("{operation} finished");
+            *running = None;
+        }
+    }
+}
+
+/// Releases the operation lock when dropped.
+pub struct OperationGuard<'a> {
+    lock: &'a OperationLock,
+}
+
+impl OperationGuard<'_> {
+    /// keeps the lock for `duration` after the request finished, while the
+    /// device still acts on it
+    pub fn keep(self, duration: Duration) {
+        if let Some((_, until)) = self.lock.running.lock().unwrap().as_mut() {
+            *until = Some(Instant::now() + duration);
+        }
+
+        // not released on drop
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for OperationGuard<'_> {
+    fn drop(&mut self) {
+        *self.lock.running.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn released_on_drop() {
+        let lock = OperationLock::default();
+        let guard = lock.try_acquire(Operation::Reboot).unwrap();
+
+        assert_eq!(
+            lock.try_acquire(Operation::ReloadNetwork).err(),
+            Some(Operation::Reboot)
+        );
+
+        drop(guard);
+        assert!(lock.try_acquire(Operation::ReloadNetwork).is_ok());
+    }
+
+    #[test]
+    fn kept_until_cooldown_elapsed() {
+        let lock = OperationLock::default();
+
+        lock.try_acquire(Operation::Reboot)
+            .unwrap()
+            .keep(Duration::from_secs(60));
+
+        assert_eq!(
+            lock.try_acquire(Operation::Reboot).err(),
+            Some(Operation::Reboot)
+        );
+
+        assert_eq!(
+            lock.try_acquire(Operation::ReloadNetwork).err(),
+            Some(Operation::Reboot)
+        );
+
+        let lock = OperationLock::default();
+
+        lock.try_acquire(Operation::Reboot)
+            .unwrap()
+            .keep(Duration::ZERO);
+
+        assert!(lock.try_acquire(Operation::Reboot).is_ok());
+    }
+
+    #[test]
+    fn kept_until_released() {
+        let lock = OperationLock::default();
+
+        lock.try_acquire(Operation::Reboot)
+            .unwrap()
+            .keep(Duration::from_secs(60));
+        lock.release_kept();
+
+        assert!(lock.try_acquire(Operation::Reboot).is_ok());
+    }
+
+    #[test]
+    fn running_operation_not_released() {
+        let lock = OperationLock::default();
+        let _guard = lock.try_acquire(Operation::ReloadNetwork).unwrap();
+
+        lock.release_kept();
+
+        assert_eq!(
+            lock.try_acquire(Operation::Reboot).err(),
+            Some(Operation::ReloadNetwork)
+        );
+    }
+}
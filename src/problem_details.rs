@@ -0,0 +1,13 @@
+use actix_web::{http::StatusCode, HttpResponse};
+use serde_json::json;
+
+/// RFC 7807 problem details
+pub fn problem_response(status_code: StatusCode, detail: &str) -> HttpResponse {
+    HttpResponse::build(status_code)
+        .content_type("application/problem+json")
+        .json(json!({
+            "title": status_code.canonical_reason(),
+            "status": status_code.as_u16(),
+            "detail": detail,
+        }))
+}
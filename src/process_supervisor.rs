@@ -0,0 +1,188 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, error, info, warn};
+use std::{
+    future::Future,
+    path::PathBuf,
+    pin::Pin,
+    process::{ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+};
+
+const RESTART_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+/// a child running at least that long is considered stable and resets the restart count
+const STABLE_RUNTIME: Duration = Duration::from_secs(60);
+/// consecutive failed health probes after which a child is restarted
+const UNHEALTHY_THRESHOLD: u32 = 3;
+
+type HealthProbe = Box<dyn Fn() -> Pin<Box<dyn Future<Output = bool> + Send>> + Send + Sync>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartPolicy {
+    /// [`ProcessSupervisor::run`] returns as soon as the child exited
+    Never,
+    Always,
+}
+
+/// Why a supervised child stopped.
+enum Stopped {
+    Exited(ExitStatus),
+    Unhealthy,
+}
+
+/// Spawns a child process, forwards its output to the log, restarts it according
+/// to its restart policy and optionally kills it when its health probe fails
+/// repeatedly. Restarts are delayed with exponential backoff, a child crashing
+/// again and again is given up after `max_restarts`. The child is killed when
+/// the future returned by [`ProcessSupervisor::run`] is dropped.
+pub struct ProcessSupervisor {
+    name: String,
+    program: PathBuf,
+    restart_policy: RestartPolicy,
+    max_restarts: Option<u32>,
+    health_probe: Option<(Duration, HealthProbe)>,
+}
+
+impl ProcessSupervisor {
+    pub fn new(name: &str, program: PathBuf) -> Self {
+        ProcessSupervisor {
+            name: name.to_string(),
+            program,
+            restart_policy: RestartPolicy::Never,
+            max_restarts: None,
+            health_probe: None,
+        }
+    }
+
+    pub fn restart_policy(mut self, restart_policy: RestartPolicy) -> Self {
+        self.restart_policy = restart_policy;
+        self
+    }
+
+    /// number of consecutive restarts of an unstable child before giving up
+    pub fn max_restarts(mut self, max_restarts: u32) -> Self {
+        self.max_restarts = Some(max_restarts);
+        self
+    }
+
+    /// `probe` is called every `interval` while the child is running
+    pub fn health_probe<F, Fut>(mut self, interval: Duration, probe: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + 'static,
+    {
+        self.health_probe = Some((interval, Box::new(move || Box::pin(probe()))));
+        self
+    }
+
+    /// returns the exit status of the child if it is not restarted,
+    /// an error if it could not be spawned or was given up
+    pub async fn run(self) -> Result<ExitStatus> {
+        let mut restarts = 0;
+
+        loop {
+            let started = Instant::now();
+            let mut child = self.spawn()?;
+
+            let reason = match self.supervise(&mut child).await? {
+                Stopped::Exited(status) if self.restart_policy == RestartPolicy::Never => {
+                    info!("{} exited: {status}", self.name);
+                    return Ok(status);
+                }
+                Stopped::Exited(status) => format!("exited: {status}"),
+                Stopped::Unhealthy => {
+                    child
+                        .kill()
+                        .await
+                        .with_context(|| format!("kill {} failed", self.name))?;
+                    "unhealthy".to_string()
+                }
+            };
+
+            if STABLE_RUNTIME <= started.elapsed() {
+                restarts = 0;
+            }
+
+            if self.max_restarts.is_some_and(|max| max <= restarts) {
+                bail!("{} {reason}, given up after {restarts} restarts", self.name);
+            }
+
+            let delay = (RESTART_BACKOFF_BASE * 2u32.pow(restarts.min(16))).min(MAX_RESTART_DELAY);
+            restarts += 1;
+
+            warn!("{} {reason}, restart {restarts} in {delay:?}", self.name);
+
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    fn spawn(&self) -> Result<Child> {
+        let mut child = Command::new(&self.program)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("spawn {} failed", self.name))?;
+
+        debug!("{} pid: {:?}", self.name, child.id());
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(forward_output(self.name.clone(), stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(forward_output(self.name.clone(), stderr));
+        }
+
+        Ok(child)
+    }
+
+    async fn supervise(&self, child: &mut Child) -> Result<Stopped> {
+        let Some((interval, probe)) = &self.health_probe else {
+            return Ok(Stopped::Exited(child.wait().await?));
+        };
+
+        let mut interval = tokio::time::interval(*interval);
+        let mut failures = 0;
+
+        // the first tick completes immediately, give the child time to start up
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                status = child.wait() => return Ok(Stopped::Exited(status?)),
+                _ = interval.tick() => {
+                    if probe().await {
+                        failures = 0;
+                        continue;
+                    }
+
+                    failures += 1;
+                    warn!("{} health probe failed ({failures}/{UNHEALTHY_THRESHOLD})", self.name);
+
+                    if UNHEALTHY_THRESHOLD <= failures {
+                        return Ok(Stopped::Unhealthy);
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn forward_output<R: AsyncRead + Unpin>(name: String, output: R) {
+    let mut lines = BufReader::new(output).lines();
+
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) => info!("{name}: {line}"),
+            Ok(None) => break,
+            Err(e) => {
+                error!("{name}: cannot read output: {e}");
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use actix_web::http::{Method, StatusCode};
+use log::warn;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+/// upper bounds of the latency buckets, the last bucket takes everything above
+const BUCKET_BOUNDS_MS: [u64; 10] = [5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000];
+
+#[derive(Default)]
+struct Histogram {
+    count: u64,
+    sum_ms: u64,
+    max_ms: u64,
+    buckets: [u64; BUCKET_BOUNDS_MS.len() + 1],
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HistogramSnapshot {
+    count: u64,
+    sum_ms: u64,
+    max_ms: u64,
+    /// (upper bound in ms, count), the bound of the last bucket is None
+    buckets: Vec<(Option<u64>, u64)>,
+}
+
+/// Latency histograms per route and logging of requests exceeding a threshold.
+pub struct RequestMetrics {
+    routes: Mutex<BTreeMap<String, Histogram>>,
+    slow_threshold_millis: AtomicU64,
+}
+
+impl RequestMetrics {
+    pub fn new(slow_threshold: Duration) -> Self {
+        RequestMetrics {
+            routes: Mutex::new(BTreeMap::new()),
+            slow_threshold_millis: AtomicU64::new(slow_threshold.as_millis() as u64),
+        }
+    }
+
+    pub fn set_slow_threshold(&self, slow_threshold: Duration) {
+        self.slow_threshold_millis
+            .store(slow_threshold.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// `route` is the matched route pattern in order to keep the number of histograms bound,
+    /// `path` the actual request path only used for logging
+    pub fn record(
+        &self,
+        method: &Method,
+        route: Option<&str>,
+        path: &str,
+        status: StatusCode,
+        elapsed: Duration,
+    ) {
+        let elapsed_ms = elapsed.as_millis() as u64;
+
+        if self.slow_threshold_millis.load(Ordering::Relaxed) <= elapsed_ms {
+            warn!("slow request: {method} {path} -> {status} took {elapsed:?}");
+        }
+
+        let key = format!("{method} {}", route.unwrap_or("unmatched"));
+        let bucket = BUCKET_BOUNDS_MS
+            .iter()
+            .position(|bound| elapsed_ms <= *bound)
+            .unwrap_or(BUCKET_BOUNDS_MS.len());
+
+        let mut routes = self.routes.lock().unwrap();
+        let histogram = routes.entry(key).or_default();
+
+        histogram.count += 1;
+        histogram.sum_ms += elapsed_ms;
+        histogram.max_ms = histogram.max_ms.max(elapsed_ms);
+        histogram.buckets[bucket] += 1;
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, HistogramSnapshot> {
+        self.routes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(route, histogram)| {
+                let bounds = BUCKET_BOUNDS_MS.iter().map(|bound| Some(*bound));
+
+                (
+                    route.clone(),
+                    HistogramSnapshot {
+                        count: histogram.count,
+                        sum_ms: histogram.sum_ms,
+                        max_ms: histogram.max_ms,
+                        buckets: bounds
+                            .chain(std::iter::once(None))
+                            .zip(histogram.buckets)
+                            .collect(),
+                    },
+                )
+            })
+            .collect()
+    }
+}
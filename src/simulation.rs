@@ -0,0 +1,101 @@
+use crate::centrifugo::Centrifugo;
+use actix_web::http::StatusCode;
+use anyhow::Result;
+use log::{error, info};
+use serde_json::json;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const REBOOT_DURATION: Duration = Duration::from_secs(15);
+const RELOAD_NETWORK_DURATION: Duration = Duration::from_secs(3);
+
+/// Scripted omnect-device-service behavior for running omnect-ui without a
+/// device (`--demo`). Instead of omnect-device-service the simulation publishes
+/// fake device status to centrifugo.
+pub struct Simulation {
+    centrifugo: Arc<Centrifugo>,
+    /// the simulated device service is unreachable until then, e.g. while rebooting
+    offline_until: Mutex<Option<Instant>>,
+}
+
+impl Simulation {
+    pub fn new(centrifugo: Arc<Centrifugo>) -> Self {
+        Simulation {
+            centrifugo,
+            offline_until: Mutex::new(None),
+        }
+    }
+
+    pub fn reachable(&self) -> bool {
+        self.offline_until
+            .lock()
+            .unwrap()
+            .is_none_or(|until| until <= Instant::now())
+    }
+
+    pub async fn post(&self, path: &str) -> Result<(StatusCode, String)> {
+        info!("simulation: post {path}");
+
+        match path {
+            "/republish/v1" => publish_status(&self.centrifugo, true).await?,
+            "/reboot/v1" => {
+                publish_online(&self.centrifugo, false).await?;
+                self.recover_after(REBOOT_DURATION);
+            }
+            "/reload-network/v1" => {
+                publish_online(&self.centrifugo, false).await?;
+                self.recover_after(RELOAD_NETWORK_DURATION);
+            }
+            _ => return Ok((StatusCode::NOT_FOUND, format!("{path} not simulated"))),
+        }
+
+        Ok((StatusCode::OK, String::new()))
+    }
+
+    fn recover_after(&self, duration: Duration) {
+        *self.offline_until.lock().unwrap() = Some(Instant::now() + duration);
+
+        let centrifugo = self.centrifugo.clone();
+
+        actix_rt::spawn(async move {
+            tokio::time::sleep(duration).await;
+
+            if let Err(e) = publish_status(&centrifugo, true).await {
+                error!("simulation: publish status failed: {e:#}");
+            }
+        });
+    }
+}
+
+async fn publish_status(centrifugo: &Centrifugo, online: bool) -> Result<()> {
+    centrifugo
+        .publish(
+            "Versions",
+            json!({
+                "os-version": {
+                    "osName": "OMNECT-gateway-devel",
+                    "swVersion": "4.0.17.123456789"
+                },
+                "omnect-device-service-version": "0.15.0",
+                "azure-sdk-version": "0.13.0"
+            }),
+        )
+        .await?;
+
+    centrifugo
+        .publish(
+            "Timeouts",
+            json!({ "wait-online-timeout": { "nanos": 0, "secs": 300 } }),
+        )
+        .await?;
+
+    publish_online(centrifugo, online).await
+}
+
+async fn publish_online(centrifugo: &Centrifugo, online: bool) -> Result<()> {
+    centrifugo
+        .publish("OnlineStatus", json!({ "iothub": online }))
+        .await
+}
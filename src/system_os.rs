@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{collections::BTreeMap, io::ErrorKind};
+
+/// os-release of the host, the one in /etc is the one of the container image
+const HOST_OS_RELEASE_PATH: &str = "/host/os-release";
+const KERNEL_RELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+const CPUINFO_PATH: &str = "/proc/cpuinfo";
+const MEMINFO_PATH: &str = "/proc/meminfo";
+/// cpuinfo keys naming the cpu, x86 uses "model name", arm e.g. "Model" or "Hardware"
+const CPU_MODEL_KEYS: [&str; 4] = ["model name", "Model", "Hardware", "cpu model"];
+
+/// OS and platform details of the device, read from the os-release mounted
+/// from the host and /proc, which reflects the host kernel.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct OsDetails {
+    /// all os-release variables, e.g. NAME, VERSION_ID, PRETTY_NAME, None if
+    /// the host os-release is not mounted
+    os_release: Option<BTreeMap<String, String>>,
+    kernel_version: String,
+    architecture: &'static str,
+    cpu_model: Option<String>,
+    cpu_count: usize,
+    total_memory_kib: Option<u64>,
+}
+
+impl OsDetails {
+    pub fn read() -> Result<Self> {
+        let os_release = match std::fs::read_to_string(HOST_OS_RELEASE_PATH) {
+            Ok(content) => Some(parse_os_release(&content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e).context(format!("cannot read {HOST_OS_RELEASE_PATH}")),
+        };
+        let kernel_version = std::fs::read_to_string(KERNEL_RELEASE_PATH)
+            .with_context(|| format!("cannot read {KERNEL_RELEASE_PATH}"))?;
+        let cpuinfo = std::fs::read_to_string(CPUINFO_PATH)
+            .with_context(|| format!("cannot read {CPUINFO_PATH}"))?;
+        let meminfo = std::fs::read_to_string(MEMINFO_PATH)
+            .with_context(|| format!("cannot read {MEMINFO_PATH}"))?;
+
+        Ok(OsDetails {
+            os_release,
+            kernel_version: kernel_version.trim().to_string(),
+            architecture: std::env::consts::ARCH,
+            cpu_model: CPU_MODEL_KEYS
+                .iter()
+                .find_map(|key| proc_value(&cpuinfo, key))
+                .map(str::to_string),
+            cpu_count: cpuinfo
+                .lines()
+                .filter(|line| line.split(':').next().unwrap().trim() == "processor")
+                .count(),
+            total_memory_kib: proc_value(&meminfo, "MemTotal")
+                .and_then(|value| value.trim_end_matches("kB").trim().parse().ok()),
+        })
+    }
+}
+
+/// `KEY=value` lines, values may be quoted
+fn parse_os_release(content: &str) -> BTreeMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| {
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value);
+
+            (key.to_string(), value.replace("\\\"", "\""))
+        })
+        .collect()
+}
+
+/// value of the first `key : value` line of a /proc file
+pub fn proc_value<'a>(content: &'a str, key: &str) -> Option<&'a str> {
+    content.lines().find_map(|line| {
+        let (k, v) = line.split_once(':')?;
+        (k.trim() == key).then_some(v.trim())
+    })
+}
@@ -0,0 +1,165 @@
+use crate::system_os::proc_value;
+use log::warn;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    ffi::CString,
+    mem::MaybeUninit,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+pub const MAX_HISTORY_HOURS: u64 = 24;
+const MAX_SAMPLES: usize = (MAX_HISTORY_HOURS * 3600 / SAMPLE_INTERVAL.as_secs()) as usize;
+const STAT_PATH: &str = "/proc/stat";
+const MEMINFO_PATH: &str = "/proc/meminfo";
+
+/// Usage of the device at a time, None if it could not be read.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Sample {
+    /// seconds since unix epoch
+    timestamp: u64,
+    /// since the previous sample
+    cpu_percent: Option<f32>,
+    memory_percent: Option<f32>,
+    /// of the file system holding the data dir
+    data_dir_percent: Option<f32>,
+}
+
+/// Keeps the last 24 hours of usage samples, so that trends are available
+/// although no browser was open at the time. The history is kept in memory
+/// only, persisting a sample per minute would wear the flash.
+pub struct TelemetryHistory {
+    samples: Mutex<VecDeque<Sample>>,
+    data_dir: PathBuf,
+}
+
+impl TelemetryHistory {
+    pub fn new(data_dir: &Path) -> Self {
+        TelemetryHistory {
+            samples: Mutex::new(VecDeque::with_capacity(MAX_SAMPLES)),
+            data_dir: data_dir.to_path_buf(),
+        }
+    }
+
+    /// samples of the last `hours`, oldest first
+    pub fn history(&self, hours: u64) -> Vec<Sample> {
+        let since = now().saturating_sub(hours * 3600);
+
+        self.samples
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|sample| since <= sample.timestamp)
+            .copied()
+            .collect()
+    }
+
+    fn push(&self, sample: Sample) {
+        let mut samples = self.samples.lock().unwrap();
+
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+}
+
+/// Samples cpu, memory and data dir usage in the background.
+pub async fn sample_telemetry(history: Arc<TelemetryHistory>) {
+    let mut interval = tokio::time::interval(SAMPLE_INTERVAL);
+    let mut last_cpu_times = None;
+
+    loop {
+        interval.tick().await;
+
+        let cpu_times = cpu_times();
+
+        history.push(Sample {
+            timestamp: now(),
+            cpu_percent: cpu_times.zip(last_cpu_times).and_then(
+                |((busy, total), (last_busy, last_total))| {
+                    let total = total.saturating_sub(last_total);
+                    (0 < total).then(|| percent(busy.saturating_sub(last_busy), total))
+                },
+            ),
+            memory_percent: memory_percent(),
+            data_dir_percent: disk_percent(&history.data_dir),
+        });
+
+        last_cpu_times = cpu_times;
+    }
+}
+
+/// (busy, total) jiffies of all cpus since boot
+fn cpu_times() -> Option<(u64, u64)> {
+    let stat = std::fs::read_to_string(STAT_PATH)
+        .inspect_err(|e| warn!("cannot read {STAT_PATH}: {e}"))
+        .ok()?;
+    let times = stat
+        .lines()
+        .next()?
+        .strip_prefix("cpu ")?
+        .split_whitespace()
+        .map(|value| value.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    // user nice system idle iowait ...
+    let idle = times.get(3)? + times.get(4).unwrap_or(&0);
+    let total = times.iter().sum::<u64>();
+
+    Some((total - idle, total))
+}
+
+fn memory_percent() -> Option<f32> {
+    let meminfo = std::fs::read_to_string(MEMINFO_PATH)
+        .inspect_err(|e| warn!("cannot read {MEMINFO_PATH}: {e}"))
+        .ok()?;
+    let kib = |key| {
+        proc_value(&meminfo, key)?
+            .trim_end_matches("kB")
+            .trim()
+            .parse::<u64>()
+            .ok()
+    };
+    let total = kib("MemTotal")?;
+    let available = kib("MemAvailable")?;
+
+    (0 < total).then(|| percent(total.saturating_sub(available), total))
+}
+
+/// used space like df, i.e. relative to the space available to unprivileged users
+fn disk_percent(path: &Path) -> Option<f32> {
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: path is nul terminated and stat is initialized on success
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            warn!("statvfs failed: {}", std::io::Error::last_os_error());
+            return None;
+        }
+
+        stat.assume_init()
+    };
+
+    let used = (stat.f_blocks - stat.f_bfree) as u64;
+    let total = used + stat.f_bavail as u64;
+
+    (0 < total).then(|| percent(used, total))
+}
+
+fn percent(part: u64, total: u64) -> f32 {
+    (part as f64 * 100.0 / total as f64) as f32
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
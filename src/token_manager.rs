@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Context, Result};
+use jwt_simple::prelude::*;
+use log::warn;
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use zeroize::Zeroizing;
+
+const TOKEN_EXPIRE_HOURES: u64 = 2;
+pub const TOKEN_SUBJECT: &str = "omnect-ui";
+const TOKEN_VERSION_FILE: &str = "token_version";
+
+#[derive(Default, Serialize, Deserialize)]
+struct SessionClaims {
+    #[serde(default)]
+    remember: bool,
+    #[serde(default)]
+    version: u64,
+    /// seconds since unix epoch of the login the token descends from
+    #[serde(default)]
+    login: u64,
+}
+
+/// Properties of a verified token.
+pub struct Session {
+    pub remember: bool,
+    login: u64,
+}
+
+/// Creates and verifies the tokens used for the omnect-ui API and centrifugo.
+/// Every token carries the token version it was created with. Bumping the
+/// version revokes all tokens issued before.
+pub struct TokenManager {
+    remember_me_max_age: Option<Duration>,
+    version: AtomicU64,
+    version_path: PathBuf,
+}
+
+impl TokenManager {
+    /// `remember_me_max_age` enables long living tokens on explicit request of the user
+    pub fn new(remember_me_max_age: Option<std::time::Duration>, data_dir: &Path) -> Self {
+        let version_path = data_dir.join(TOKEN_VERSION_FILE);
+        let version = match std::fs::read_to_string(&version_path) {
+            Ok(version) => version.trim().parse().unwrap_or_else(|e| {
+                warn!("invalid {}: {e}", version_path.display());
+                0
+            }),
+            Err(_) => 0,
+        };
+
+        TokenManager {
+            remember_me_max_age: remember_me_max_age.map(|max_age| max_age.into()),
+            version: AtomicU64::new(version),
+            version_path,
+        }
+    }
+
+    /// invalidates all tokens issued so far
+    pub fn revoke_all(&self) -> Result<()> {
+        let version = self.version.fetch_add(1, Ordering::SeqCst) + 1;
+
+        std::fs::write(&self.version_path, version.to_string()).with_context(|| {
+            format!(
+                "revoked in memory only, cannot persist {}",
+                self.version_path.display()
+            )
+        })
+    }
+
+    pub fn create_token(&self, remember: bool) -> Result<String> {
+        let (validity, remember) = match self.remember_me_max_age {
+            Some(max_age) if remember => (max_age, true),
+            _ => (Duration::from_hours(TOKEN_EXPIRE_HOURES), false),
+        };
+
+        self.issue(remember, Clock::now_since_epoch().as_secs(), validity)
+    }
+
+    /// returns a new token of `session`, None if a remembered session reached
+    /// its max age. A refreshed token expires with the max age of the login it
+    /// descends from, refreshing must not keep a remembered session alive forever.
+    pub fn refresh_token(&self, session: &Session) -> Result<Option<String>> {
+        let (validity, remember) = match self.remember_me_max_age {
+            Some(max_age) if session.remember => {
+                let expires = Duration::from_secs(session.login) + max_age;
+                let now = Clock::now_since_epoch();
+
+                if expires <= now {
+                    return Ok(None);
+                }
+
+                (max_age.min(expires - now), true)
+            }
+            _ => (Duration::from_hours(TOKEN_EXPIRE_HOURES), false),
+        };
+
+        self.issue(remember, session.login, validity).map(Some)
+    }
+
+    fn issue(&self, remember: bool, login: u64, validity: Duration) -> Result<String> {
+        let claims = Claims::with_custom_claims(
+            SessionClaims {
+                remember,
+                version: self.version.load(Ordering::SeqCst),
+                login,
+            },
+            validity,
+        )
+        .with_subject(TOKEN_SUBJECT);
+
+        key()?
+            .authenticate(claims)
+            .map_err(|e| anyhow!("cannot create token: {e}"))
+    }
+
+    /// returns the session of a valid token, None otherwise
+    pub fn verify_token(&self, token: &str) -> Result<Option<Session>> {
+        let key = key()?;
+        let max_validity = self
+            .remember_me_max_age
+            .unwrap_or_default()
+            .max(Duration::from_hours(TOKEN_EXPIRE_HOURES));
+        let options = VerificationOptions {
+            accept_future: true,
+            time_tolerance: Some(Duration::from_mins(15)),
+            max_validity: Some(max_validity),
+            required_subject: Some(TOKEN_SUBJECT.to_string()),
+            ..Default::default()
+        };
+
+        Ok(key
+            .verify_token::<SessionClaims>(token, Some(options))
+            .ok()
+            .filter(|claims| claims.custom.version == self.version.load(Ordering::SeqCst))
+            .map(|claims| Session {
+                remember: claims.custom.remember,
+                login: claims.custom.login,
+            }))
+    }
+}
+
+fn key() -> Result<HS256Key> {
+    let key = Zeroizing::new(
+        std::env::var("CENTRIFUGO_TOKEN_HMAC_SECRET_KEY").context("missing jwt secret")?,
+    );
+
+    Ok(HS256Key::from_bytes(key.as_bytes()))
+}
@@ -0,0 +1,69 @@
+use log::warn;
+use serde::Serialize;
+use std::{path::Path, time::Duration};
+use tokio::process::Command;
+
+const CENTRIFUGO_VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Version details of omnect-ui and its components. Git revision and build
+/// timestamp are taken from the build environment (`GIT_SHORT_REV`,
+/// `BUILD_TIMESTAMP`) and are None for local builds.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct VersionInfo {
+    version: &'static str,
+    git_rev: Option<&'static str>,
+    build_timestamp: Option<&'static str>,
+    centrifugo_version: Option<String>,
+    features: Vec<&'static str>,
+}
+
+impl VersionInfo {
+    pub async fn new(centrifugo: &Path, demo: bool, remember_me: bool) -> Self {
+        let mut features = vec![];
+
+        if demo {
+            features.push("demo");
+        }
+        if remember_me {
+            features.push("remember-me");
+        }
+
+        VersionInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            git_rev: option_env!("GIT_SHORT_REV"),
+            build_timestamp: option_env!("BUILD_TIMESTAMP"),
+            centrifugo_version: centrifugo_version(centrifugo).await,
+            features,
+        }
+    }
+}
+
+/// `centrifugo version` prints e.g. "Centrifugo v5.3.2 (Go version: go1.22.2)"
+async fn centrifugo_version(centrifugo: &Path) -> Option<String> {
+    let output = Command::new(centrifugo)
+        .arg("version")
+        .kill_on_drop(true)
+        .output();
+
+    let output = match tokio::time::timeout(CENTRIFUGO_VERSION_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => output,
+        Ok(Ok(output)) => {
+            warn!("centrifugo version: {}", output.status);
+            return None;
+        }
+        Ok(Err(e)) => {
+            warn!("centrifugo version: {e}");
+            return None;
+        }
+        Err(_) => {
+            warn!("centrifugo version: timeout");
+            return None;
+        }
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .nth(1)
+        .map(|version| version.trim_start_matches('v').to_string())
+}
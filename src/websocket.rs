@@ -0,0 +1,173 @@
+use crate::{centrifugo::Centrifugo, token_manager::TokenManager};
+use actix_web::{http::header::ORIGIN, web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, MessageStream, Session};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error, warn};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async_tls_with_config,
+    tungstenite::{client::IntoClientRequest, http::HeaderValue, Message},
+    Connector, MaybeTlsStream, WebSocketStream,
+};
+
+type Upstream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Relays websocket connections on the ui port to the local centrifugo instance,
+/// so that clients only have to reach a single TLS port.
+/// centrifugo only checks the signature and expiry of a connection token. That's
+/// why the token of the connect command is verified by the relay, which also
+/// rejects tokens revoked by /logout/all.
+pub async fn ws(
+    req: HttpRequest,
+    body: web::Payload,
+    centrifugo: web::Data<Centrifugo>,
+    token_manager: web::Data<TokenManager>,
+) -> actix_web::Result<HttpResponse> {
+    debug!("ws() called");
+
+    let mut upstream_request = centrifugo
+        .websocket_url()
+        .into_client_request()
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // centrifugo checks the origin against CENTRIFUGO_ALLOWED_ORIGINS, so it must
+    // see the origin of the client and not the relay
+    if let Some(origin) = req.headers().get(ORIGIN) {
+        let origin = HeaderValue::from_bytes(origin.as_bytes())
+            .map_err(actix_web::error::ErrorBadRequest)?;
+
+        upstream_request.headers_mut().insert("Origin", origin);
+    }
+
+    let (upstream, _) = connect_async_tls_with_config(
+        upstream_request,
+        None,
+        false,
+        Some(Connector::Rustls(centrifugo.tls_config())),
+    )
+    .await
+    .map_err(|e| {
+        error!("ws: cannot connect to centrifugo: {e}");
+        actix_web::error::ErrorBadGateway("centrifugo unreachable")
+    })?;
+
+    let (response, session, client) = actix_ws::handle(&req, body)?;
+
+    actix_rt::spawn(relay_messages(
+        session,
+        client,
+        upstream,
+        token_manager.into_inner(),
+    ));
+
+    Ok(response)
+}
+
+async fn relay_messages(
+    mut session: Session,
+    mut client: MessageStream,
+    upstream: Upstream,
+    token_manager: Arc<TokenManager>,
+) {
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+    let mut close_reason: Option<CloseReason> = None;
+    let mut authorized = false;
+
+    loop {
+        tokio::select! {
+            msg = client.next() => match msg {
+                Some(Ok(actix_ws::Message::Text(text))) => {
+                    if !authorized {
+                        if !connect_authorized(&text, &token_manager) {
+                            warn!("ws: connect rejected, invalid token");
+                            close_reason = Some(CloseCode::Policy.into());
+                            break;
+                        }
+
+                        authorized = true;
+                    }
+
+                    if upstream_tx.send(Message::Text(text.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                // the json protocol is used, a binary connect command cannot be verified
+                Some(Ok(actix_ws::Message::Binary(_))) if !authorized => {
+                    warn!("ws: connect rejected, binary protocol not supported");
+                    close_reason = Some(CloseCode::Unsupported.into());
+                    break;
+                }
+                Some(Ok(actix_ws::Message::Binary(bytes))) => {
+                    if upstream_tx.send(Message::Binary(bytes.to_vec())).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(actix_ws::Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(actix_ws::Message::Close(reason))) => {
+                    close_reason = reason;
+                    break;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    error!("ws: client stream failed: {e}");
+                    break;
+                }
+                None => break,
+            },
+            msg = upstream_rx.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    if session.text(text).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Binary(bytes))) => {
+                    if session.binary(bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    error!("ws: centrifugo stream failed: {e}");
+                    break;
+                }
+            }
+        }
+    }
+
+    let _ = upstream_tx.close().await;
+    let _ = session.close(close_reason).await;
+
+    debug!("ws: connection closed");
+}
+
+/// true if the first message of a client contains a connect command with a valid
+/// token. Messages of the centrifugo json protocol carry one command per line.
+fn connect_authorized(text: &str, token_manager: &TokenManager) -> bool {
+    let mut tokens = text
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter_map(|command| {
+            command.get("connect").map(|connect| {
+                connect
+                    .get("token")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            })
+        })
+        .peekable();
+
+    if tokens.peek().is_none() {
+        return false;
+    }
+
+    tokens.all(|token| {
+        token.is_some_and(|token| matches!(token_manager.verify_token(&token), Ok(Some(_))))
+    })
+}